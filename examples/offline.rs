@@ -0,0 +1,30 @@
+#[cfg(not(target_arch = "wasm32"))]
+fn main() {
+    use quad_snd::{OfflineContext, PlaySoundParams, Sound};
+
+    let mut ctx = OfflineContext::new();
+    let sound = Sound::load(&ctx, include_bytes!("test.ogg"));
+
+    let playback = sound.play(
+        &ctx,
+        PlaySoundParams {
+            looped: true,
+            volume: 1.0,
+            ..Default::default()
+        },
+    );
+    let mut samples = ctx.render(44100);
+
+    playback.set_volume(&ctx, 0.3);
+    samples.extend(ctx.render(44100));
+
+    playback.stop(&ctx);
+    samples.extend(ctx.render(44100 / 2));
+
+    let file = std::fs::File::create("offline.wav").unwrap();
+    quad_snd::write_wav(std::io::BufWriter::new(file), &samples).unwrap();
+}
+
+// offline rendering needs the native mixer
+#[cfg(target_arch = "wasm32")]
+fn main() {}
@@ -6,12 +6,18 @@ let sounds = new Map();
 let playbacks = [];
 let sound_key_next = 1;
 let playback_key_next = 1;
+let buses = new Map();
+let bus_key_next = 1;
+let limiter_nodes = [];
 
 function audio_init() {
     if (audio_context == null) {
         audio_context = new AudioContext();
         let audio_listener = audio_context.listener;
 
+        // master bus, key 0
+        buses.set(0, create_bus("Master", null));
+
         {
             let AudioContext = window.AudioContext || window.webkitAudioContext;
             let ctx = new AudioContext();
@@ -76,6 +82,173 @@ function audio_add_buffer(content, content_len) {
     return sound_key;
 }
 
+// soundfields are not decoded, only the omnidirectional W channel is kept
+function audio_add_ambisonic_buffer(content, content_len, w_gain) {
+    let content_array = wasm_memory.buffer.slice(content, content + content_len);
+
+    let sound_key = sound_key_next;
+    sound_key_next += 1;
+
+    audio_context.decodeAudioData(content_array, function(buffer) {
+        let omni = audio_context.createBuffer(1, buffer.length, buffer.sampleRate);
+        let w = buffer.getChannelData(0);
+        let samples = omni.getChannelData(0);
+
+        for (let i = 0; i < w.length; i++) {
+            samples[i] = w[i] * w_gain;
+        }
+        sounds.set(sound_key, omni);
+    }, function(e) {
+        // fail
+        console.error("Failed to decode audio buffer", e);
+    });
+    return sound_key;
+}
+
+// voices (playbacks routed to this bus, solo gate) -> output (volume, mute) -> parent output
+function create_bus(name, parent) {
+    let bus = {
+        name: name,
+        parent: parent,
+        volume: 1,
+        muted: false,
+        solo: false,
+        voices: audio_context.createGain(),
+        output: audio_context.createGain(),
+    };
+
+    bus.voices.connect(bus.output);
+    bus.output.connect(parent != null ? parent.output : audio_context.destination);
+
+    return bus;
+}
+
+function update_bus_gain(bus) {
+    bus.output.gain.setValueAtTime(bus.muted ? 0 : bus.volume, audio_context.currentTime);
+}
+
+// same as the native mixer: while any bus is soloed only the playbacks
+// of soloed buses and their children are heard
+function update_solo() {
+    let any_solo = Array.from(buses.values()).some(bus => bus.solo);
+
+    buses.forEach(bus => {
+        let soloed = false;
+        for (let b = bus; b != null; b = b.parent) {
+            soloed = soloed || b.solo;
+        }
+
+        let audible = !any_solo || soloed;
+        bus.voices.gain.setValueAtTime(audible ? 1 : 0, audio_context.currentTime);
+    });
+}
+
+function audio_bus_new(name, name_len, parent_key) {
+    let name_bytes = new Uint8Array(wasm_memory.buffer, name, name_len);
+    let parent = buses.get(parent_key) || buses.get(0);
+
+    let bus_key = bus_key_next;
+    bus_key_next += 1;
+
+    buses.set(bus_key, create_bus(new TextDecoder().decode(name_bytes), parent));
+    update_solo();
+
+    return bus_key;
+}
+
+function audio_bus_find(name, name_len) {
+    let name_bytes = new Uint8Array(wasm_memory.buffer, name, name_len);
+    let name_string = new TextDecoder().decode(name_bytes);
+
+    for (let [bus_key, bus] of buses) {
+        if (bus.name === name_string) {
+            return bus_key;
+        }
+    }
+    return -1;
+}
+
+function audio_bus_set_volume(bus_key, volume) {
+    let bus = buses.get(bus_key);
+
+    if (bus != null) {
+        bus.volume = volume;
+        update_bus_gain(bus);
+    }
+}
+
+function audio_bus_set_muted(bus_key, muted) {
+    let bus = buses.get(bus_key);
+
+    if (bus != null) {
+        bus.muted = muted;
+        update_bus_gain(bus);
+    }
+}
+
+function audio_bus_set_solo(bus_key, solo) {
+    let bus = buses.get(bus_key);
+
+    if (bus != null) {
+        bus.solo = solo;
+        update_solo();
+    }
+}
+
+// same as the native mixer's soft clipper
+function soft_clip(sample, ceiling) {
+    let knee = ceiling * 0.9;
+    let magnitude = Math.abs(sample);
+
+    if (magnitude <= knee) {
+        return sample;
+    }
+
+    let range = ceiling - knee;
+    return Math.sign(sample) * (knee + range * Math.tanh((magnitude - knee) / range));
+}
+
+// master output -> [compressor -> [soft clipper]] -> destination
+function audio_set_limiter(enabled, ceiling, release, soft_clip_enabled) {
+    let master = buses.get(0);
+
+    master.output.disconnect();
+    limiter_nodes.forEach(node => node.disconnect());
+    limiter_nodes = [];
+
+    let last = master.output;
+
+    if (enabled) {
+        // not a true limiter: a 20:1 ratio with a fixed built-in lookahead,
+        // short peaks still get through over the ceiling
+        let compressor = audio_context.createDynamicsCompressor();
+        compressor.threshold.value = ceiling;
+        compressor.knee.value = 0;
+        compressor.ratio.value = 20;
+        compressor.attack.value = 0;
+        compressor.release.value = release;
+        limiter_nodes.push(compressor);
+
+        if (soft_clip_enabled) {
+            let ceiling_gain = Math.pow(10, ceiling / 20);
+            let curve = new Float32Array(1025);
+            for (let i = 0; i < curve.length; i++) {
+                curve[i] = soft_clip(i / 512 - 1, ceiling_gain);
+            }
+
+            let clipper = audio_context.createWaveShaper();
+            clipper.curve = curve;
+            limiter_nodes.push(clipper);
+        }
+    }
+
+    limiter_nodes.forEach(node => {
+        last.connect(node);
+        last = node;
+    });
+    last.connect(audio_context.destination);
+}
+
 function audio_source_is_loaded(sound_key) {
     return sounds.has(sound_key) && sounds.get(sound_key) != undefined;
 }
@@ -91,8 +264,22 @@ function recycle_playback() {
             playback_key: 0,
             source: audio_context.createBufferSource(),
             gain_node: audio_context.createGain(),
+            // fade out of stop_with_fade, apart from the volume so that volume changes keep it
+            stop_gain: audio_context.createGain(),
             ended: null,
+            // position in the sound was `anchor_position` at `anchor_time`
+            // and is moving `playbackRate` times faster than real time since then
+            anchor_time: 0,
+            anchor_position: 0,
+            paused_at: null,
+            // pending stop_with_fade: the fade `curve` ends and the source stops at audio time `end`,
+            // or `remaining` seconds after resuming while paused
+            stopping: null,
+            pan_linear: false,
+            // aux send gains, connected from the merger
+            sends: [],
         };
+        connect_panner(playback);
 
         playbacks.push(playback);
     }
@@ -100,53 +287,234 @@ function recycle_playback() {
     return playback;
 }
 
+// stop_gain -> gain_node -> splitter -> left/right gains -> merger
+// mono sources are upmixed to stereo by the gain node
+function connect_panner(playback) {
+    playback.stop_gain.connect(playback.gain_node);
+
+    playback.gain_node.channelCount = 2;
+    playback.gain_node.channelCountMode = 'explicit';
+    playback.gain_node.channelInterpretation = 'speakers';
+
+    playback.splitter = audio_context.createChannelSplitter(2);
+    playback.left_gain = audio_context.createGain();
+    playback.right_gain = audio_context.createGain();
+    playback.merger = audio_context.createChannelMerger(2);
+
+    playback.gain_node.connect(playback.splitter);
+    playback.splitter.connect(playback.left_gain, 0);
+    playback.splitter.connect(playback.right_gain, 1);
+    playback.left_gain.connect(playback.merger, 0, 0);
+    playback.right_gain.connect(playback.merger, 0, 1);
+}
+
+// same gains as the native mixer, 1.0 for both channels when centered
+function set_pan(playback, pan) {
+    pan = Math.min(Math.max(pan, -1), 1);
+
+    if (playback.pan_linear) {
+        playback.left_gain.gain.value = Math.min(1 - pan, 1);
+        playback.right_gain.gain.value = Math.min(1 + pan, 1);
+    } else {
+        let angle = (pan + 1) * Math.PI / 4;
+
+        playback.left_gain.gain.value = Math.SQRT2 * Math.cos(angle);
+        playback.right_gain.gain.value = Math.SQRT2 * Math.sin(angle);
+    }
+}
+
+// same shapes as the native mixer's FadeCurve: Linear, Exponential, SCurve, EqualPower
+function fade_value(curve, from, to, t) {
+    switch (curve) {
+        case 1: {
+            // -60dB, silence can't be reached exponentially
+            let floor = 0.001;
+            let log_from = Math.log(Math.max(from, floor));
+            let log_to = Math.log(Math.max(to, floor));
+
+            return Math.exp(log_from + (log_to - log_from) * t);
+        }
+        case 2:
+            return from + (to - from) * t * t * (3 - 2 * t);
+        case 3: {
+            let angle = t * Math.PI / 2;
+
+            if (to >= from) {
+                return from + (to - from) * Math.sin(angle);
+            }
+            return from + (to - from) * (1 - Math.cos(angle));
+        }
+        default:
+            return from + (to - from) * t;
+    }
+}
+
+function set_gain(gain, volume) {
+    gain.cancelScheduledValues(audio_context.currentTime);
+    gain.setValueAtTime(volume, audio_context.currentTime);
+}
+
+function fade(gain, from, to, duration, curve) {
+    if (duration <= 0) {
+        set_gain(gain, to);
+        return;
+    }
+
+    let values = new Float32Array(64);
+    for (let i = 0; i < values.length; i++) {
+        values[i] = fade_value(curve, from, to, i / (values.length - 1));
+    }
+
+    gain.cancelScheduledValues(audio_context.currentTime);
+    gain.setValueCurveAtTime(values, audio_context.currentTime, duration);
+}
+
+function release_source(playback) {
+    playback.source.removeEventListener('ended', playback.ended);
+    try {
+        // source may be already stopped by a previous pause
+        playback.source.stop();
+    } catch (e) {}
+    playback.source.disconnect();
+}
+
+// AudioBufferSourceNode can be started only once, so seeking or resuming
+// means replacing it with a new one, starting at the given offset
+function restart_source(playback, offset) {
+    let source = audio_context.createBufferSource();
+    source.buffer = playback.source.buffer;
+    source.loop = playback.source.loop;
+    source.playbackRate.value = playback.source.playbackRate.value;
+
+    release_source(playback);
+
+    playback.source = source;
+    start_source(playback, offset);
+
+    // the new source has to stop at the end of a pending fade out too
+    if (playback.stopping != null) {
+        playback.source.stop(playback.stopping.end);
+    }
+}
+
+function pause(playback) {
+    if (playback.paused_at == null) {
+        playback.paused_at = playback_position(playback);
+        release_source(playback);
+
+        // freeze a pending fade out where it is, it goes on after resuming
+        if (playback.stopping != null) {
+            let stop_gain = playback.stop_gain.gain;
+
+            playback.stopping.remaining = playback.stopping.end - audio_context.currentTime;
+            set_gain(stop_gain, stop_gain.value);
+        }
+    }
+}
+
+function resume(playback) {
+    if (playback.paused_at != null) {
+        let offset = playback.paused_at;
+        let stopping = playback.stopping;
+
+        playback.paused_at = null;
+
+        if (stopping != null) {
+            if (stopping.remaining <= 0) {
+                stop(playback);
+                return;
+            }
+
+            let stop_gain = playback.stop_gain.gain;
+            stopping.end = audio_context.currentTime + stopping.remaining;
+            fade(stop_gain, stop_gain.value, 0, stopping.remaining, stopping.curve);
+        }
+        restart_source(playback, offset);
+    }
+}
+
+function playback_position(playback) {
+    if (playback.paused_at != null) {
+        return playback.paused_at;
+    }
+
+    let duration = playback.source.buffer.duration;
+    let elapsed = audio_context.currentTime - playback.anchor_time;
+    let position = playback.anchor_position + elapsed * playback.source.playbackRate.value;
+
+    if (playback.source.loop) {
+        return position % duration;
+    }
+    return Math.min(position, duration);
+}
+
 function stop(playback) {
     try {
         playback.source.removeEventListener('ended', playback.ended);
 
         playback.source.disconnect();
-        playback.gain_node.disconnect();
+        playback.merger.disconnect();
+        playback.sends.forEach(send => send.disconnect());
+        playback.sends = [];
 
         playback.sound_key = 0;
         playback.playback_key = 0;
+        playback.stopping = null;
     } catch (e) {
         console.error("Error stopping sound", e);
     }
 }
 
-function audio_play_buffer(sound_key, volume, repeat) {
+function start_source(playback, offset) {
+    playback.source.connect(playback.stop_gain);
+    playback.source.addEventListener('ended', playback.ended);
+
+    try {
+        playback.source.start(0, offset);
+        playback.anchor_time = audio_context.currentTime;
+        playback.anchor_position = offset;
+    } catch (e) {
+        console.error("Error starting sound", e);
+    }
+}
+
+function audio_play_buffer(sound_key, volume, repeat, offset, pitch, pan, pan_linear, fade_in, fade_in_curve, bus_key) {
     let playback_key = playback_key_next++;
 
     let pb = recycle_playback();
 
     pb.sound_key = sound_key;
     pb.playback_key = playback_key;
+    pb.paused_at = null;
 
-    pb.source.connect(pb.gain_node);
-    pb.gain_node.connect(audio_context.destination);
+    pb.merger.connect((buses.get(bus_key) || buses.get(0)).voices);
 
-    pb.gain_node.gain.value = volume;
+    set_gain(pb.stop_gain.gain, 1);
+    fade(pb.gain_node.gain, 0, volume, fade_in, fade_in_curve);
+    pb.pan_linear = pan_linear;
+    set_pan(pb, pan);
     pb.source.loop = repeat;
+    pb.source.playbackRate.value = pitch;
+    pb.source.buffer = sounds.get(sound_key);
 
     pb.ended = function() {
         stop(pb);
     };
-    pb.source.addEventListener('ended', pb.ended);
-
-    try {
-        pb.source.buffer = sounds.get(sound_key);
-        pb.source.start(0);
-    } catch (e) {
-        console.error("Error starting sound", e);
-    }
+    start_source(pb, offset);
 
     return playback_key;
 }
 
+function audio_source_duration(sound_key) {
+    let buffer = sounds.get(sound_key);
+
+    return buffer != null ? buffer.duration : 0;
+}
+
 function audio_source_set_volume(sound_key, volume) {
     playbacks.forEach(playback => {
         if (playback.sound_key === sound_key) {
-            playback.gain_node.gain.value = volume;
+            set_gain(playback.gain_node.gain, volume);
         }
     });
 }
@@ -157,6 +525,18 @@ function audio_source_stop(sound_key) {
     });
 }
 
+function audio_source_pause(sound_key) {
+    playbacks.forEach(playback => {
+        playback.sound_key === sound_key && pause(playback);
+    });
+}
+
+function audio_source_resume(sound_key) {
+    playbacks.forEach(playback => {
+        playback.sound_key === sound_key && resume(playback);
+    });
+}
+
 function audio_source_delete(sound_key) {
     audio_source_stop(sound_key);
 
@@ -172,20 +552,171 @@ function audio_playback_stop(playback_key) {
 function audio_playback_set_volume(playback_key, volume) {
     let playback = playbacks.find(playback => playback.playback_key === playback_key);
 
+    playback != null && set_gain(playback.gain_node.gain, volume);
+}
+
+function audio_playback_fade_to(playback_key, volume, duration, curve) {
+    let playback = find_playback(playback_key);
+
     if (playback != null) {
-        playback.gain_node.gain.value = volume;
+        let gain = playback.gain_node.gain;
+
+        fade(gain, gain.value, volume, duration, curve);
+    }
+}
+
+function audio_playback_stop_with_fade(playback_key, duration, curve) {
+    let playback = find_playback(playback_key);
+
+    if (playback == null) {
+        return;
     }
+
+    if (playback.paused_at != null || duration <= 0) {
+        stop(playback);
+        return;
+    }
+
+    let stop_gain = playback.stop_gain.gain;
+    playback.stopping = {
+        end: audio_context.currentTime + duration,
+        curve: curve,
+        remaining: 0,
+    };
+
+    fade(stop_gain, stop_gain.value, 0, duration, curve);
+    // "ended" listener will stop the playback once the fade is over
+    playback.source.stop(playback.stopping.end);
+}
+
+function find_playback(playback_key) {
+    return playbacks.find(playback => playback.playback_key === playback_key);
+}
+
+function audio_playback_seek(playback_key, position) {
+    let playback = find_playback(playback_key);
+
+    if (playback == null) {
+        return;
+    }
+
+    if (playback.paused_at != null) {
+        playback.paused_at = position;
+    } else {
+        restart_source(playback, position);
+    }
+}
+
+function audio_playback_set_pan(playback_key, pan) {
+    let playback = find_playback(playback_key);
+
+    playback != null && set_pan(playback, pan);
+}
+
+function audio_playback_add_send(playback_key, bus_key, level) {
+    let playback = find_playback(playback_key);
+    let bus = buses.get(bus_key);
+
+    if (playback != null && bus != null) {
+        let send = audio_context.createGain();
+
+        send.gain.value = level;
+        playback.merger.connect(send);
+        send.connect(bus.voices);
+        playback.sends.push(send);
+    }
+}
+
+function audio_playback_set_pitch(playback_key, pitch) {
+    let playback = find_playback(playback_key);
+
+    if (playback != null) {
+        if (playback.paused_at == null) {
+            playback.anchor_position = playback_position(playback);
+            playback.anchor_time = audio_context.currentTime;
+        }
+        playback.source.playbackRate.value = pitch;
+    }
+}
+
+function audio_playback_pause(playback_key) {
+    let playback = find_playback(playback_key);
+
+    playback != null && pause(playback);
+}
+
+function audio_playback_resume(playback_key) {
+    let playback = find_playback(playback_key);
+
+    playback != null && resume(playback);
+}
+
+function audio_playback_is_paused(playback_key) {
+    let playback = find_playback(playback_key);
+
+    return playback != null && playback.paused_at != null;
+}
+
+function audio_playback_is_playing(playback_key) {
+    return find_playback(playback_key) != null;
+}
+
+function audio_playback_position(playback_key) {
+    let playback = find_playback(playback_key);
+
+    if (playback == null || playback.source.buffer == null) {
+        return 0;
+    }
+
+    return playback_position(playback);
+}
+
+function audio_playback_remaining(playback_key) {
+    let playback = find_playback(playback_key);
+
+    if (playback == null || playback.source.buffer == null) {
+        return 0;
+    }
+
+    let left = playback.source.buffer.duration - audio_playback_position(playback_key);
+    if (left <= 0) {
+        return 0;
+    }
+
+    return left / playback.source.playbackRate.value;
 }
 
 miniquad_add_plugin({
     register_plugin: function (importObject) {
         importObject.env.audio_init = audio_init;
+        importObject.env.audio_set_limiter = audio_set_limiter;
+        importObject.env.audio_bus_new = audio_bus_new;
+        importObject.env.audio_bus_find = audio_bus_find;
+        importObject.env.audio_bus_set_volume = audio_bus_set_volume;
+        importObject.env.audio_bus_set_muted = audio_bus_set_muted;
+        importObject.env.audio_bus_set_solo = audio_bus_set_solo;
         importObject.env.audio_add_buffer = audio_add_buffer;
+        importObject.env.audio_add_ambisonic_buffer = audio_add_ambisonic_buffer;
         importObject.env.audio_play_buffer = audio_play_buffer;
         importObject.env.audio_source_is_loaded = audio_source_is_loaded;
         importObject.env.audio_source_set_volume = audio_source_set_volume;
         importObject.env.audio_source_stop = audio_source_stop;
+        importObject.env.audio_source_pause = audio_source_pause;
+        importObject.env.audio_source_resume = audio_source_resume;
         importObject.env.audio_source_delete = audio_source_delete;
+        importObject.env.audio_source_duration = audio_source_duration;
         importObject.env.audio_playback_stop = audio_playback_stop;
         importObject.env.audio_playback_set_volume = audio_playback_set_volume;
+        importObject.env.audio_playback_fade_to = audio_playback_fade_to;
+        importObject.env.audio_playback_stop_with_fade = audio_playback_stop_with_fade;
+        importObject.env.audio_playback_set_pitch = audio_playback_set_pitch;
+        importObject.env.audio_playback_set_pan = audio_playback_set_pan;
+        importObject.env.audio_playback_add_send = audio_playback_add_send;
+        importObject.env.audio_playback_seek = audio_playback_seek;
+        importObject.env.audio_playback_pause = audio_playback_pause;
+        importObject.env.audio_playback_resume = audio_playback_resume;
+        importObject.env.audio_playback_is_paused = audio_playback_is_paused;
+        importObject.env.audio_playback_is_playing = audio_playback_is_playing;
+        importObject.env.audio_playback_position = audio_playback_position;
+        importObject.env.audio_playback_remaining = audio_playback_remaining;
 }, version: 1, name: "macroquad_audio" });
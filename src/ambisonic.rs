@@ -0,0 +1,196 @@
+//! First order ambisonics: soundfield recordings, turned with the listener's head
+//! and decoded to stereo or binaural.
+//!
+//! Soundfields are kept in AmbiX channel order and normalization: W, Y, Z, X with SN3D.
+//! X points to the front of the recording, Y to the left and Z up. The front of the
+//! recording is world -Z and its top is world +Y, matching the default `Listener`.
+
+use crate::{
+    hrtf::HrtfSet,
+    spatial::{self, Panning},
+    Listener,
+};
+
+use std::f32::consts::SQRT_2;
+
+/// Weight of the first order components of the binaural decoder, max-rE:
+/// slightly wider images, much less sound leaking to the opposite side.
+const MAX_RE: f32 = 0.577_350_26;
+
+/// Channel order and normalization of a 4 channel B-format recording.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AmbisonicFormat {
+    /// W, Y, Z, X with SN3D normalization, used by most current tools and 360° video.
+    AmbiX,
+    /// Furse-Malham: W, X, Y, Z with W 3 dB lower, used by older recordings.
+    FuMa,
+}
+
+impl AmbisonicFormat {
+    /// Convert interleaved 4 channel frames to AmbiX, in place.
+    pub(crate) fn to_ambix(self, samples: &mut [f32]) {
+        if self == AmbisonicFormat::FuMa {
+            for frame in samples.chunks_exact_mut(4) {
+                let [w, x, y, z] = [frame[0], frame[1], frame[2], frame[3]];
+
+                frame.copy_from_slice(&[w * SQRT_2, y, z, x]);
+            }
+        }
+    }
+}
+
+/// Rows of a 3x3 matrix, turning first order components from the recording
+/// into the listener's view: X front, Y left, Z up.
+pub(crate) type Rotation = [[f32; 3]; 3];
+
+pub(crate) fn rotation(listener: &Listener) -> Rotation {
+    // recorded front, left, up axes as the listener sees them
+    let column = |[front, left, up]: [f32; 3]| {
+        let [right, up, back] = spatial::to_listener_axes(listener, [-left, up, -front]);
+
+        [-back, -right, up]
+    };
+    let columns = [
+        column([1.0, 0.0, 0.0]),
+        column([0.0, 1.0, 0.0]),
+        column([0.0, 0.0, 1.0]),
+    ];
+
+    [0, 1, 2].map(|row| columns.map(|column| column[row]))
+}
+
+/// Binaural decoding filters: head related impulse responses of virtual loudspeakers
+/// at the corners of a cube, weighted by their decoding gains and summed per channel.
+/// Convolving the soundfield with them is the same as decoding to the loudspeakers
+/// and rendering each one with the HRTF, for a fraction of the cost.
+#[derive(Debug, Clone)]
+pub(crate) struct AmbisonicFilters {
+    /// Time reversed, per AmbiX channel and ear.
+    reversed: [[Vec<f32>; 2]; 4],
+    frames: usize,
+}
+
+impl AmbisonicFilters {
+    pub fn new(set: &HrtfSet) -> AmbisonicFilters {
+        let frames = if set.is_empty() { 0 } else { set.frames() };
+        let mut reversed = [(); 4].map(|_| [vec![0.0; frames], vec![0.0; frames]]);
+
+        let mut speakers = vec![];
+        for &x in &[-1.0, 1.0] {
+            for &y in &[-1.0, 1.0] {
+                for &z in &[-1.0, 1.0] {
+                    speakers.push(spatial::normalize([x, y, z]));
+                }
+            }
+        }
+
+        for &speaker in &speakers {
+            let response = match set.reversed_response(speaker) {
+                Some(response) => response,
+                None => continue,
+            };
+            // listener space to front, left, up
+            let [right, up, back] = speaker;
+            let weight = 3.0 * MAX_RE / speakers.len() as f32;
+            let gains = [
+                1.0 / speakers.len() as f32,
+                -right * weight,
+                up * weight,
+                -back * weight,
+            ];
+
+            for (filter, gain) in reversed.iter_mut().zip(gains) {
+                for (filter, response) in filter.iter_mut().zip(response) {
+                    for (filter, sample) in filter.iter_mut().zip(response) {
+                        *filter += sample * gain;
+                    }
+                }
+            }
+        }
+
+        AmbisonicFilters { reversed, frames }
+    }
+}
+
+/// Per playback soundfield renderer.
+#[derive(Debug)]
+pub(crate) struct AmbisonicDecoder {
+    /// Rotations at the start and the end of the current buffer.
+    from: Rotation,
+    to: Option<Rotation>,
+    /// Rotated input history per channel, stored twice to always have `frames` contiguous samples.
+    history: [Vec<f32>; 4],
+    position: usize,
+}
+
+impl AmbisonicDecoder {
+    pub fn new() -> AmbisonicDecoder {
+        AmbisonicDecoder {
+            from: [[0.0; 3]; 3],
+            to: None,
+            history: [vec![], vec![], vec![], vec![]],
+            position: 0,
+        }
+    }
+
+    /// Start a new buffer, turning the soundfield from the previous rotation to `rotation`.
+    pub fn set_rotation(&mut self, rotation: Rotation) {
+        self.from = self.to.replace(rotation).unwrap_or(rotation);
+    }
+
+    /// Decode an AmbiX `frame` to stereo, `t` from 0.0 to 1.0 is the position in the buffer.
+    ///
+    /// `Panning::Stereo` uses virtual cardioid microphones pointing left and right,
+    /// `Panning::Hrtf` the binaural `filters`.
+    pub fn decode(
+        &mut self,
+        t: f32,
+        panning: Panning,
+        filters: &AmbisonicFilters,
+        frame: [f32; 4],
+    ) -> [f32; 2] {
+        let to = self.to.unwrap_or(self.from);
+        let components = [frame[3], frame[1], frame[2]];
+        let [x, y, z] = [0, 1, 2].map(|row| {
+            (0..3)
+                .map(|column| {
+                    let (a, b) = (self.from[row][column], to[row][column]);
+
+                    (a + (b - a) * t) * components[column]
+                })
+                .sum::<f32>()
+        });
+        let w = frame[0];
+
+        let frames = filters.frames;
+        // an empty HRTF set has nothing to render with
+        if panning == Panning::Stereo || frames == 0 {
+            return [0.5 * (w + y), 0.5 * (w - y)];
+        }
+
+        if self.history[0].len() != frames * 2 {
+            self.history = [(); 4].map(|_| vec![0.0; frames * 2]);
+            self.position = 0;
+        }
+        self.position = (self.position + 1) % frames;
+        for (history, &sample) in self.history.iter_mut().zip(&[w, y, z, x]) {
+            history[self.position] = sample;
+            history[self.position + frames] = sample;
+        }
+
+        let window = self.position + 1..self.position + 1 + frames;
+        [0, 1].map(|ear| {
+            self.history
+                .iter()
+                .zip(&filters.reversed)
+                .map(|(history, reversed)| {
+                    history[window.clone()]
+                        .iter()
+                        .zip(&reversed[ear])
+                        .map(|(x, h)| x * h)
+                        .sum::<f32>()
+                })
+                .sum()
+        })
+    }
+}
@@ -0,0 +1,98 @@
+//! Sidechain ducking of one bus by the output of another.
+
+use crate::{limiter::db_to_gain, Bus, SAMPLE_RATE};
+
+/// Key level is followed with this release time, in seconds,
+/// so gaps between syllables do not release the ducking.
+const KEY_RELEASE: f32 = 0.05;
+
+/// Sidechain ducking of a bus, set with `Bus::set_ducking`.
+///
+/// Typically music ducked under dialogue: whenever anything plays on the `key` bus,
+/// the ducked bus volume dips by `amount` dB and recovers once the key goes quiet.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Ducking {
+    /// Bus whose output triggers the ducking. Can not be a parent of the ducked bus.
+    pub key: Bus,
+    /// Gain reduction while the key is active, in dB.
+    pub amount: f32,
+    /// Key output level that triggers the ducking, in dBFS.
+    pub threshold: f32,
+    /// Time to reach the full gain reduction, in seconds.
+    pub attack: f32,
+    /// Time to recover after the key falls below the threshold, in seconds.
+    pub release: f32,
+}
+
+impl Ducking {
+    pub fn new(key: Bus) -> Ducking {
+        Ducking {
+            key,
+            amount: 12.0,
+            threshold: -40.0,
+            attack: 0.05,
+            release: 0.5,
+        }
+    }
+}
+
+/// `Ducking` settings turned into a running DSP state.
+///
+/// While the key level is above the threshold, the gain moves towards
+/// `-amount` dB at a constant rate in dB, reaching it in `attack` seconds.
+/// Once the key falls below the threshold, it recovers in `release` seconds.
+#[derive(Debug)]
+pub(crate) struct Ducker {
+    pub key: u32,
+    amount: f32,
+    threshold: f32,
+    /// dB per frame.
+    attack_step: f32,
+    /// dB per frame.
+    release_step: f32,
+    key_decay: f32,
+    key_level: f32,
+    /// Current gain reduction, in dB, 0.0 or negative.
+    gain_db: f32,
+    gain: f32,
+}
+
+impl Ducker {
+    pub fn new(ducking: &Ducking) -> Ducker {
+        let amount = ducking.amount.abs();
+        let frames = |seconds: f32| (seconds * SAMPLE_RATE as f32).max(1.0);
+
+        Ducker {
+            key: ducking.key.id,
+            amount,
+            threshold: db_to_gain(ducking.threshold),
+            attack_step: amount / frames(ducking.attack),
+            release_step: amount / frames(ducking.release),
+            key_decay: (-1.0 / frames(KEY_RELEASE)).exp(),
+            key_level: 0.0,
+            gain_db: 0.0,
+            gain: 1.0,
+        }
+    }
+
+    /// Duck interleaved stereo `buffer` in place, `key` is the key bus output.
+    pub fn process(&mut self, buffer: &mut [f32], key: &[f32]) {
+        for (frame, key) in buffer.chunks_exact_mut(2).zip(key.chunks_exact(2)) {
+            let peak = key[0].abs().max(key[1].abs());
+            self.key_level = peak.max(self.key_level * self.key_decay);
+
+            let gain_db = if self.key_level > self.threshold {
+                (self.gain_db - self.attack_step).max(-self.amount)
+            } else {
+                (self.gain_db + self.release_step).min(0.0)
+            };
+            if gain_db != self.gain_db {
+                self.gain_db = gain_db;
+                self.gain = db_to_gain(gain_db);
+            }
+
+            frame[0] *= self.gain;
+            frame[1] *= self.gain;
+        }
+    }
+}
@@ -0,0 +1,226 @@
+//! RBJ cookbook biquad filters.
+//! https://www.w3.org/TR/audio-eq-cookbook/
+
+use super::{AudioEffect, Smoothed, UPDATE_FRAMES};
+use crate::{limiter::db_to_gain, SAMPLE_RATE};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FilterType {
+    LowPass,
+    HighPass,
+    /// Constant 0 dB peak gain band-pass.
+    BandPass,
+    /// Boosts or cuts frequencies below the cutoff by `gain` dB.
+    LowShelf,
+    /// Boosts or cuts frequencies above the cutoff by `gain` dB.
+    HighShelf,
+    Notch,
+}
+
+/// Normalized biquad coefficients, `a0` is 1.0.
+#[derive(Debug, Clone, Copy)]
+struct Coefficients {
+    b0: f32,
+    b1: f32,
+    b2: f32,
+    a1: f32,
+    a2: f32,
+}
+
+impl Coefficients {
+    fn new(kind: FilterType, cutoff: f32, q: f32, gain: f32) -> Coefficients {
+        let nyquist = SAMPLE_RATE as f32 / 2.0;
+        let w0 =
+            2.0 * std::f32::consts::PI * cutoff.clamp(10.0, nyquist * 0.99) / SAMPLE_RATE as f32;
+        let (sin, cos) = w0.sin_cos();
+        let alpha = sin / (2.0 * q.max(0.01));
+        // amplitude for shelving filters, sqrt of the linear gain
+        let a = db_to_gain(gain / 2.0);
+
+        let (b0, b1, b2, a0, a1, a2) = match kind {
+            FilterType::LowPass => (
+                (1.0 - cos) / 2.0,
+                1.0 - cos,
+                (1.0 - cos) / 2.0,
+                1.0 + alpha,
+                -2.0 * cos,
+                1.0 - alpha,
+            ),
+            FilterType::HighPass => (
+                (1.0 + cos) / 2.0,
+                -(1.0 + cos),
+                (1.0 + cos) / 2.0,
+                1.0 + alpha,
+                -2.0 * cos,
+                1.0 - alpha,
+            ),
+            FilterType::BandPass => (alpha, 0.0, -alpha, 1.0 + alpha, -2.0 * cos, 1.0 - alpha),
+            FilterType::Notch => (1.0, -2.0 * cos, 1.0, 1.0 + alpha, -2.0 * cos, 1.0 - alpha),
+            FilterType::LowShelf => {
+                let sqrt_a = 2.0 * a.sqrt() * alpha;
+
+                (
+                    a * ((a + 1.0) - (a - 1.0) * cos + sqrt_a),
+                    2.0 * a * ((a - 1.0) - (a + 1.0) * cos),
+                    a * ((a + 1.0) - (a - 1.0) * cos - sqrt_a),
+                    (a + 1.0) + (a - 1.0) * cos + sqrt_a,
+                    -2.0 * ((a - 1.0) + (a + 1.0) * cos),
+                    (a + 1.0) + (a - 1.0) * cos - sqrt_a,
+                )
+            }
+            FilterType::HighShelf => {
+                let sqrt_a = 2.0 * a.sqrt() * alpha;
+
+                (
+                    a * ((a + 1.0) + (a - 1.0) * cos + sqrt_a),
+                    -2.0 * a * ((a - 1.0) + (a + 1.0) * cos),
+                    a * ((a + 1.0) + (a - 1.0) * cos - sqrt_a),
+                    (a + 1.0) - (a - 1.0) * cos + sqrt_a,
+                    2.0 * ((a - 1.0) - (a + 1.0) * cos),
+                    (a + 1.0) - (a - 1.0) * cos - sqrt_a,
+                )
+            }
+        };
+
+        Coefficients {
+            b0: b0 / a0,
+            b1: b1 / a0,
+            b2: b2 / a0,
+            a1: a1 / a0,
+            a2: a2 / a0,
+        }
+    }
+}
+
+/// Stereo biquad filter effect.
+///
+/// Cutoff, Q and gain changes are smoothed, so they can be automated
+/// with `Effect::set_parameter` every frame without zipper noise.
+#[derive(Debug, Clone)]
+pub struct BiquadFilter {
+    kind: FilterType,
+    cutoff: Smoothed,
+    q: Smoothed,
+    gain: Smoothed,
+    coefficients: Coefficients,
+    /// Transposed direct form II state, per channel.
+    state: [[f32; 2]; 2],
+    /// Frames left until the next coefficients update.
+    countdown: usize,
+}
+
+impl BiquadFilter {
+    /// Cutoff (or center) frequency, in Hz.
+    pub const CUTOFF: u32 = 0;
+    pub const Q: u32 = 1;
+    /// Shelf gain, in dB. Ignored by other filter types.
+    pub const GAIN: u32 = 2;
+
+    pub fn new(kind: FilterType, cutoff: f32, q: f32) -> BiquadFilter {
+        BiquadFilter {
+            kind,
+            cutoff: Smoothed::new(cutoff),
+            q: Smoothed::new(q),
+            gain: Smoothed::new(0.0),
+            coefficients: Coefficients::new(kind, cutoff, q, 0.0),
+            state: [[0.0; 2]; 2],
+            countdown: 0,
+        }
+    }
+
+    pub fn low_pass(cutoff: f32) -> BiquadFilter {
+        BiquadFilter::new(FilterType::LowPass, cutoff, std::f32::consts::FRAC_1_SQRT_2)
+    }
+
+    pub fn high_pass(cutoff: f32) -> BiquadFilter {
+        BiquadFilter::new(
+            FilterType::HighPass,
+            cutoff,
+            std::f32::consts::FRAC_1_SQRT_2,
+        )
+    }
+
+    pub fn band_pass(center: f32, q: f32) -> BiquadFilter {
+        BiquadFilter::new(FilterType::BandPass, center, q)
+    }
+
+    pub fn notch(center: f32, q: f32) -> BiquadFilter {
+        BiquadFilter::new(FilterType::Notch, center, q)
+    }
+
+    pub fn low_shelf(cutoff: f32, gain: f32) -> BiquadFilter {
+        BiquadFilter::new(
+            FilterType::LowShelf,
+            cutoff,
+            std::f32::consts::FRAC_1_SQRT_2,
+        )
+        .with_gain(gain)
+    }
+
+    pub fn high_shelf(cutoff: f32, gain: f32) -> BiquadFilter {
+        BiquadFilter::new(
+            FilterType::HighShelf,
+            cutoff,
+            std::f32::consts::FRAC_1_SQRT_2,
+        )
+        .with_gain(gain)
+    }
+
+    /// Shelf gain, in dB.
+    pub fn with_gain(mut self, gain: f32) -> BiquadFilter {
+        self.gain = Smoothed::new(gain);
+        self.update_coefficients();
+        self
+    }
+
+    pub fn kind(&self) -> FilterType {
+        self.kind
+    }
+
+    fn update_coefficients(&mut self) {
+        self.coefficients =
+            Coefficients::new(self.kind, self.cutoff.value, self.q.value, self.gain.value);
+    }
+}
+
+impl AudioEffect for BiquadFilter {
+    fn process(&mut self, buffer: &mut [f32]) {
+        for frame in buffer.chunks_exact_mut(2) {
+            if self.countdown == 0 {
+                self.countdown = UPDATE_FRAMES;
+
+                let cutoff = self.cutoff.advance(UPDATE_FRAMES);
+                let q = self.q.advance(UPDATE_FRAMES);
+                let gain = self.gain.advance(UPDATE_FRAMES);
+                if cutoff || q || gain {
+                    self.update_coefficients();
+                }
+            }
+            self.countdown -= 1;
+
+            let c = self.coefficients;
+            for (sample, state) in frame.iter_mut().zip(&mut self.state) {
+                let input = *sample;
+                let output = c.b0 * input + state[0];
+
+                state[0] = c.b1 * input - c.a1 * output + state[1];
+                state[1] = c.b2 * input - c.a2 * output;
+
+                *sample = output;
+            }
+        }
+    }
+
+    fn reset(&mut self) {
+        self.state = [[0.0; 2]; 2];
+    }
+
+    fn set_parameter(&mut self, index: u32, value: f32) {
+        match index {
+            BiquadFilter::CUTOFF => self.cutoff.target = value,
+            BiquadFilter::Q => self.q.target = value,
+            BiquadFilter::GAIN => self.gain.target = value,
+            _ => {}
+        }
+    }
+}
@@ -0,0 +1,189 @@
+//! Feed-forward dynamics compressor.
+//! Gain computer and smoothing after Giannoulis, Massberg and Reiss,
+//! "Digital Dynamic Range Compressor Design - A Tutorial and Analysis".
+
+use super::{AudioEffect, Smoothed, UPDATE_FRAMES};
+use crate::{limiter::db_to_gain, SAMPLE_RATE};
+
+/// Averaging time of the RMS detector, in seconds.
+const RMS_WINDOW: f32 = 0.01;
+
+/// How the input level is measured.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Detection {
+    /// Reacts to every peak, tighter control.
+    Peak,
+    /// Follows the average loudness, smoother and more transparent.
+    Rms,
+}
+
+/// `Compressor` parameters, all of them can be changed later with `Effect::set_parameter`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct CompressorSettings {
+    /// Level above which the signal gets compressed, in dBFS.
+    pub threshold: f32,
+    /// Input to output level ratio above the threshold, 4.0 means 4 dB over the threshold become 1 dB.
+    pub ratio: f32,
+    /// Width of the soft transition around the threshold, in dB. 0.0 is a hard knee.
+    pub knee: f32,
+    /// Time to react to a level increase, in seconds.
+    pub attack: f32,
+    /// Time to recover after the level drops, in seconds.
+    pub release: f32,
+    /// Gain applied after compression, in dB.
+    pub makeup: f32,
+    pub detection: Detection,
+}
+
+impl Default for CompressorSettings {
+    fn default() -> CompressorSettings {
+        CompressorSettings {
+            threshold: -18.0,
+            ratio: 4.0,
+            knee: 6.0,
+            attack: 0.01,
+            release: 0.1,
+            makeup: 0.0,
+            detection: Detection::Peak,
+        }
+    }
+}
+
+/// Stereo linked compressor: both channels always get the same gain.
+#[derive(Debug, Clone)]
+pub struct Compressor {
+    threshold: f32,
+    ratio: f32,
+    knee: f32,
+    attack_coef: f32,
+    release_coef: f32,
+    makeup: Smoothed,
+    detection: Detection,
+    /// Smoothed mean square of the input, for RMS detection.
+    mean_square: f32,
+    rms_coef: f32,
+    /// Smoothed gain reduction, in dB, 0.0 or negative.
+    reduction: f32,
+    /// Frames left until the next parameters update.
+    countdown: usize,
+}
+
+impl Compressor {
+    /// Threshold, in dBFS.
+    pub const THRESHOLD: u32 = 0;
+    pub const RATIO: u32 = 1;
+    /// Knee width, in dB.
+    pub const KNEE: u32 = 2;
+    /// Attack, in seconds.
+    pub const ATTACK: u32 = 3;
+    /// Release, in seconds.
+    pub const RELEASE: u32 = 4;
+    /// Makeup gain, in dB.
+    pub const MAKEUP: u32 = 5;
+    /// 0.0 for `Detection::Peak`, 1.0 for `Detection::Rms`.
+    pub const DETECTION: u32 = 6;
+
+    pub fn new(settings: CompressorSettings) -> Compressor {
+        Compressor {
+            threshold: settings.threshold,
+            ratio: settings.ratio.max(1.0),
+            knee: settings.knee.max(0.0),
+            attack_coef: time_to_coef(settings.attack),
+            release_coef: time_to_coef(settings.release),
+            makeup: Smoothed::new(settings.makeup),
+            detection: settings.detection,
+            mean_square: 0.0,
+            rms_coef: time_to_coef(RMS_WINDOW),
+            reduction: 0.0,
+            countdown: 0,
+        }
+    }
+
+    /// Static curve: gain reduction in dB for an input level in dB.
+    fn gain_reduction(&self, level: f32) -> f32 {
+        let over = level - self.threshold;
+        let slope = 1.0 / self.ratio - 1.0;
+
+        if 2.0 * over < -self.knee {
+            0.0
+        } else if self.knee > 0.0 && 2.0 * over.abs() <= self.knee {
+            let x = over + self.knee / 2.0;
+            slope * x * x / (2.0 * self.knee)
+        } else {
+            slope * over
+        }
+    }
+}
+
+impl Default for Compressor {
+    fn default() -> Compressor {
+        Compressor::new(CompressorSettings::default())
+    }
+}
+
+impl AudioEffect for Compressor {
+    fn process(&mut self, buffer: &mut [f32]) {
+        for frame in buffer.chunks_exact_mut(2) {
+            if self.countdown == 0 {
+                self.countdown = UPDATE_FRAMES;
+                self.makeup.advance(UPDATE_FRAMES);
+            }
+            self.countdown -= 1;
+
+            let level = match self.detection {
+                Detection::Peak => frame[0].abs().max(frame[1].abs()),
+                Detection::Rms => {
+                    let square = (frame[0] * frame[0] + frame[1] * frame[1]) * 0.5;
+                    self.mean_square = square + (self.mean_square - square) * self.rms_coef;
+                    self.mean_square.sqrt()
+                }
+            };
+            let level = 20.0 * level.max(1e-6).log10();
+
+            let target = self.gain_reduction(level);
+            let coef = if target < self.reduction {
+                self.attack_coef
+            } else {
+                self.release_coef
+            };
+            self.reduction = target + (self.reduction - target) * coef;
+
+            let gain = db_to_gain(self.reduction + self.makeup.value);
+            frame[0] *= gain;
+            frame[1] *= gain;
+        }
+    }
+
+    fn reset(&mut self) {
+        self.mean_square = 0.0;
+        self.reduction = 0.0;
+    }
+
+    fn set_parameter(&mut self, index: u32, value: f32) {
+        match index {
+            Compressor::THRESHOLD => self.threshold = value,
+            Compressor::RATIO => self.ratio = value.max(1.0),
+            Compressor::KNEE => self.knee = value.max(0.0),
+            Compressor::ATTACK => self.attack_coef = time_to_coef(value),
+            Compressor::RELEASE => self.release_coef = time_to_coef(value),
+            Compressor::MAKEUP => self.makeup.target = value,
+            Compressor::DETECTION => {
+                self.detection = if value >= 0.5 {
+                    Detection::Rms
+                } else {
+                    Detection::Peak
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// One-pole smoothing coefficient for a time constant in seconds.
+fn time_to_coef(seconds: f32) -> f32 {
+    if seconds <= 0.0 {
+        return 0.0;
+    }
+
+    (-1.0 / (seconds * SAMPLE_RATE as f32)).exp()
+}
@@ -0,0 +1,275 @@
+//! Convolution reverb, uniformly partitioned overlap-save in the frequency domain.
+
+use super::{
+    fft::{Complex, Fft},
+    AudioEffect,
+};
+#[cfg(not(target_arch = "wasm32"))]
+use crate::mixer::load_samples_from_file;
+use crate::SAMPLE_RATE;
+
+use std::any::Any;
+
+/// Input is processed in blocks of this many frames, which is also the latency.
+const BLOCK_FRAMES: usize = 512;
+const FFT_SIZE: usize = BLOCK_FRAMES * 2;
+/// Spectra of real signals are symmetric, only the first half is stored.
+const BINS: usize = BLOCK_FRAMES + 1;
+
+/// Recorded response of a room, to be used by `Convolver`.
+///
+/// The expensive preparation happens here, on the calling thread,
+/// so creating one does not interrupt the audio.
+#[derive(Debug, Clone)]
+pub struct ImpulseResponse {
+    /// Spectra of consecutive `BLOCK_FRAMES` long pieces of the response, per channel.
+    partitions: [Vec<Vec<Complex>>; 2],
+    frames: usize,
+}
+
+impl ImpulseResponse {
+    /// Decode a .wav or .ogg file, the same way as `Sound::load` does.
+    /// Mono responses are applied to both channels.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn load(data: &[u8]) -> ImpulseResponse {
+        ImpulseResponse::from_samples(&load_samples_from_file(data).unwrap())
+    }
+
+    /// Response from interleaved stereo samples at the mixer sample rate.
+    pub fn from_samples(samples: &[f32]) -> ImpulseResponse {
+        let fft = Fft::new(FFT_SIZE);
+        let frames = samples.len() / 2;
+        let mut buffer = vec![Complex::default(); FFT_SIZE];
+
+        let partitions = [0, 1].map(|channel| {
+            samples
+                .chunks(BLOCK_FRAMES * 2)
+                .map(|block| {
+                    buffer.iter_mut().for_each(|bin| *bin = Complex::default());
+                    for (bin, frame) in buffer.iter_mut().zip(block.chunks_exact(2)) {
+                        bin.re = frame[channel];
+                    }
+
+                    fft.forward(&mut buffer);
+                    buffer[..BINS].to_vec()
+                })
+                .collect()
+        });
+
+        ImpulseResponse { partitions, frames }
+    }
+
+    /// Length of the response, in seconds.
+    pub fn duration(&self) -> f32 {
+        self.frames as f32 / SAMPLE_RATE as f32
+    }
+}
+
+/// Stereo convolution reverb.
+///
+/// Output is fully wet, use `Effect::set_mix` to blend in the dry signal,
+/// or put the convolver on an aux send bus.
+/// The impulse response can be replaced at any time with `Effect::set_data`,
+/// the output crossfades from the old response to the new one over one block.
+/// Responses longer than the convolver was created for are cut short.
+#[derive(Debug, Clone)]
+pub struct Convolver {
+    fft: Fft,
+    response: Box<ImpulseResponse>,
+    /// Response replaced by the last swap. Crossfaded from during the following block,
+    /// then kept until the next swap hands it back to be dropped on the control side.
+    previous: Option<Box<ImpulseResponse>>,
+    crossfading: bool,
+    /// Last two blocks of input, per channel. The second half is being filled.
+    input: [Vec<f32>; 2],
+    /// Block of output being played, per channel.
+    output: [Vec<f32>; 2],
+    /// Frame in the current block.
+    position: usize,
+    /// Spectra of the most recent input blocks, a ring buffer per channel.
+    /// Allocated once, for the longest response the convolver accepts.
+    history: [Vec<Vec<Complex>>; 2],
+    /// Newest block in `history`.
+    history_index: usize,
+    spectrum: Vec<Complex>,
+    /// Output of the previous response, while crossfading.
+    crossfade: Vec<f32>,
+}
+
+impl Convolver {
+    /// Convolver with room for `response`, longer responses sent later are cut to its length.
+    pub fn new(response: ImpulseResponse) -> Convolver {
+        let duration = response.duration();
+
+        Convolver::with_max_duration(response, duration)
+    }
+
+    /// Convolver accepting responses up to `max_duration` seconds long,
+    /// or as long as `response` if it is longer.
+    pub fn with_max_duration(response: ImpulseResponse, max_duration: f32) -> Convolver {
+        let max_blocks = (max_duration.max(0.0) * SAMPLE_RATE as f32 / BLOCK_FRAMES as f32).ceil();
+        let history_len = response.partitions[0].len().max(max_blocks as usize).max(1);
+
+        Convolver {
+            fft: Fft::new(FFT_SIZE),
+            response: Box::new(response),
+            previous: None,
+            crossfading: false,
+            input: [vec![0.0; FFT_SIZE], vec![0.0; FFT_SIZE]],
+            output: [vec![0.0; BLOCK_FRAMES], vec![0.0; BLOCK_FRAMES]],
+            position: 0,
+            history: [
+                vec![vec![Complex::default(); BINS]; history_len],
+                vec![vec![Complex::default(); BINS]; history_len],
+            ],
+            history_index: 0,
+            spectrum: vec![Complex::default(); FFT_SIZE],
+            crossfade: vec![0.0; BLOCK_FRAMES],
+        }
+    }
+
+    /// Delay between the input and the output, in seconds.
+    /// Also available from `Effect::latency_seconds` once the convolver is added.
+    pub fn latency_seconds(&self) -> f32 {
+        self.latency() as f32 / SAMPLE_RATE as f32
+    }
+
+    /// Swap in `response`, returning the one replaced by the previous swap.
+    fn set_response(&mut self, response: Box<ImpulseResponse>) -> Option<Box<ImpulseResponse>> {
+        let replaced = std::mem::replace(&mut self.response, response);
+        self.crossfading = true;
+
+        self.previous.replace(replaced)
+    }
+
+    fn process_block(&mut self) {
+        let history_len = self.history[0].len();
+        self.history_index = (self.history_index + history_len - 1) % history_len;
+
+        for channel in 0..2 {
+            for (bin, &sample) in self.spectrum.iter_mut().zip(&self.input[channel]) {
+                *bin = Complex::new(sample, 0.0);
+            }
+            self.fft.forward(&mut self.spectrum);
+            self.history[channel][self.history_index].copy_from_slice(&self.spectrum[..BINS]);
+
+            let history = &self.history[channel];
+            convolve(
+                &self.fft,
+                history,
+                self.history_index,
+                &self.response.partitions[channel],
+                &mut self.spectrum,
+                &mut self.output[channel],
+            );
+
+            if let (true, Some(previous)) = (self.crossfading, &self.previous) {
+                convolve(
+                    &self.fft,
+                    history,
+                    self.history_index,
+                    &previous.partitions[channel],
+                    &mut self.spectrum,
+                    &mut self.crossfade,
+                );
+
+                for (i, (output, previous)) in self.output[channel]
+                    .iter_mut()
+                    .zip(&self.crossfade)
+                    .enumerate()
+                {
+                    let t = (i + 1) as f32 / BLOCK_FRAMES as f32;
+                    *output = previous + (*output - previous) * t;
+                }
+            }
+
+            self.input[channel].copy_within(BLOCK_FRAMES.., 0);
+        }
+
+        self.crossfading = false;
+    }
+}
+
+/// Convolve `history` of input spectra with `partitions` of a response,
+/// writing one block of output.
+fn convolve(
+    fft: &Fft,
+    history: &[Vec<Complex>],
+    newest: usize,
+    partitions: &[Vec<Complex>],
+    spectrum: &mut [Complex],
+    output: &mut [f32],
+) {
+    spectrum
+        .iter_mut()
+        .for_each(|bin| *bin = Complex::default());
+
+    for (age, partition) in partitions.iter().enumerate().take(history.len()) {
+        let input = &history[(newest + age) % history.len()];
+
+        for (sum, (&input, &response)) in spectrum.iter_mut().zip(input.iter().zip(partition)) {
+            *sum = *sum + input * response;
+        }
+    }
+
+    for bin in BINS..FFT_SIZE {
+        let mirror = spectrum[FFT_SIZE - bin];
+        spectrum[bin] = Complex::new(mirror.re, -mirror.im);
+    }
+
+    fft.inverse(spectrum);
+
+    // overlap-save: the first half is wrapped around garbage
+    for (output, bin) in output.iter_mut().zip(&spectrum[BLOCK_FRAMES..]) {
+        *output = bin.re;
+    }
+}
+
+impl AudioEffect for Convolver {
+    fn process(&mut self, buffer: &mut [f32]) {
+        for frame in buffer.chunks_exact_mut(2) {
+            for (channel, sample) in frame.iter_mut().enumerate() {
+                self.input[channel][BLOCK_FRAMES + self.position] = *sample;
+                *sample = self.output[channel][self.position];
+            }
+
+            self.position += 1;
+            if self.position == BLOCK_FRAMES {
+                self.position = 0;
+                self.process_block();
+            }
+        }
+    }
+
+    fn reset(&mut self) {
+        self.input
+            .iter_mut()
+            .flatten()
+            .for_each(|sample| *sample = 0.0);
+        self.output
+            .iter_mut()
+            .flatten()
+            .for_each(|sample| *sample = 0.0);
+        self.history
+            .iter_mut()
+            .flatten()
+            .flatten()
+            .for_each(|bin| *bin = Complex::default());
+        self.crossfading = false;
+        self.position = 0;
+    }
+
+    fn latency(&self) -> usize {
+        BLOCK_FRAMES
+    }
+
+    /// Accepts a new `ImpulseResponse`.
+    fn set_data(&mut self, data: Box<dyn Any + Send>) -> Option<Box<dyn Any + Send>> {
+        match data.downcast::<ImpulseResponse>() {
+            Ok(response) => self
+                .set_response(response)
+                .map(|replaced| replaced as Box<dyn Any + Send>),
+            Err(data) => Some(data),
+        }
+    }
+}
@@ -0,0 +1,211 @@
+//! Stereo feedback delay, for echoes.
+
+use super::{AudioEffect, Smoothed, UPDATE_FRAMES};
+use crate::SAMPLE_RATE;
+
+/// Delay lines are at least this long, in seconds, so the time can be changed later.
+const MIN_MAX_DELAY: f32 = 2.0;
+
+/// Length of a delay, either absolute or synced to the music tempo.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DelayTime {
+    Milliseconds(f32),
+    /// `beats` quarter notes at `bpm` beats per minute, 0.75 for a dotted eighth.
+    Beats {
+        beats: f32,
+        bpm: f32,
+    },
+}
+
+impl DelayTime {
+    pub fn milliseconds(&self) -> f32 {
+        match *self {
+            DelayTime::Milliseconds(ms) => ms,
+            DelayTime::Beats { beats, bpm } => beats * 60_000.0 / bpm.max(1.0),
+        }
+    }
+}
+
+/// `Delay` parameters, all of them can be changed later with `Effect::set_parameter`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DelaySettings {
+    pub time: DelayTime,
+    /// Amount of each echo fed back into the delay, from 0.0 (single echo) to below 1.0.
+    pub feedback: f32,
+    /// High frequency loss on every repeat, from 0.0 (none) to 1.0 (very dark).
+    pub damping: f32,
+    /// Echoes bounce between the left and the right channel.
+    pub ping_pong: bool,
+    /// Level of the echoes.
+    pub wet: f32,
+    /// Level of the unprocessed signal. Use 0.0 when the delay is on an aux send bus.
+    pub dry: f32,
+}
+
+impl Default for DelaySettings {
+    fn default() -> DelaySettings {
+        DelaySettings {
+            time: DelayTime::Milliseconds(375.0),
+            feedback: 0.4,
+            damping: 0.3,
+            ping_pong: false,
+            wet: 0.5,
+            dry: 1.0,
+        }
+    }
+}
+
+/// Stereo delay with feedback, damping and ping-pong.
+///
+/// Changing the time glides the delay smoothly, like a tape echo,
+/// instead of jumping and clicking.
+#[derive(Debug, Clone)]
+pub struct Delay {
+    /// Delay time, in frames.
+    time: Smoothed,
+    feedback: Smoothed,
+    damping: Smoothed,
+    ping_pong: bool,
+    wet: Smoothed,
+    dry: Smoothed,
+    /// Interleaved stereo delay line.
+    line: Vec<f32>,
+    /// Frame to be written next.
+    write: usize,
+    /// Damping filter state, per channel.
+    filter: [f32; 2],
+    /// Frames left until the next parameters update.
+    countdown: usize,
+}
+
+impl Delay {
+    /// Delay time, in milliseconds.
+    pub const TIME: u32 = 0;
+    pub const FEEDBACK: u32 = 1;
+    pub const DAMPING: u32 = 2;
+    /// 1.0 to enable ping-pong, 0.0 to disable.
+    pub const PING_PONG: u32 = 3;
+    pub const WET: u32 = 4;
+    pub const DRY: u32 = 5;
+
+    pub fn new(settings: DelaySettings) -> Delay {
+        let time = ms_to_frames(settings.time.milliseconds());
+        let frames = (time as usize).max((MIN_MAX_DELAY * SAMPLE_RATE as f32) as usize) + 2;
+
+        Delay {
+            time: Smoothed::new(time),
+            feedback: Smoothed::new(settings.feedback.clamp(0.0, 0.99)),
+            damping: Smoothed::new(settings.damping.clamp(0.0, 1.0)),
+            ping_pong: settings.ping_pong,
+            wet: Smoothed::new(settings.wet.max(0.0)),
+            dry: Smoothed::new(settings.dry.max(0.0)),
+            line: vec![0.0; frames * 2],
+            write: 0,
+            filter: [0.0; 2],
+            countdown: 0,
+        }
+    }
+
+    fn max_time(&self) -> f32 {
+        (self.line.len() / 2 - 2) as f32
+    }
+
+    /// Linearly interpolated frame `delay` frames before the write position.
+    fn read(&self, delay: f32) -> [f32; 2] {
+        let frames = self.line.len() / 2;
+        let position = self.write as f32 + frames as f32 - delay;
+        let index = position as usize;
+        let t = position - index as f32;
+        let a = index % frames;
+        let b = (index + 1) % frames;
+
+        [0, 1].map(|channel| {
+            let a = self.line[a * 2 + channel];
+            let b = self.line[b * 2 + channel];
+
+            a + (b - a) * t
+        })
+    }
+}
+
+impl Default for Delay {
+    fn default() -> Delay {
+        Delay::new(DelaySettings::default())
+    }
+}
+
+impl AudioEffect for Delay {
+    fn process(&mut self, buffer: &mut [f32]) {
+        let frames = self.line.len() / 2;
+
+        for frame in buffer.chunks_exact_mut(2) {
+            if self.countdown == 0 {
+                self.countdown = UPDATE_FRAMES;
+
+                for parameter in [
+                    &mut self.feedback,
+                    &mut self.damping,
+                    &mut self.wet,
+                    &mut self.dry,
+                ] {
+                    parameter.advance(UPDATE_FRAMES);
+                }
+            }
+            self.countdown -= 1;
+            // delay time is smoothed per frame, steps in it would be audible as clicks
+            self.time.advance(1);
+
+            let delayed = self.read(self.time.value.max(1.0));
+            let damping = self.damping.value;
+            for (filter, &delayed) in self.filter.iter_mut().zip(&delayed) {
+                *filter = delayed * (1.0 - damping) + *filter * damping;
+                if filter.abs() < 1e-20 {
+                    *filter = 0.0;
+                }
+            }
+
+            let feedback = self.feedback.value;
+            let [left, right] = [frame[0], frame[1]];
+            let written = if self.ping_pong {
+                [
+                    (left + right) * 0.5 + self.filter[1] * feedback,
+                    self.filter[0] * feedback,
+                ]
+            } else {
+                [
+                    left + self.filter[0] * feedback,
+                    right + self.filter[1] * feedback,
+                ]
+            };
+            self.line[self.write * 2] = written[0];
+            self.line[self.write * 2 + 1] = written[1];
+            self.write = (self.write + 1) % frames;
+
+            let wet = self.wet.value;
+            let dry = self.dry.value;
+            frame[0] = left * dry + delayed[0] * wet;
+            frame[1] = right * dry + delayed[1] * wet;
+        }
+    }
+
+    fn reset(&mut self) {
+        self.line.iter_mut().for_each(|sample| *sample = 0.0);
+        self.filter = [0.0; 2];
+    }
+
+    fn set_parameter(&mut self, index: u32, value: f32) {
+        match index {
+            Delay::TIME => self.time.target = ms_to_frames(value).min(self.max_time()),
+            Delay::FEEDBACK => self.feedback.target = value.clamp(0.0, 0.99),
+            Delay::DAMPING => self.damping.target = value.clamp(0.0, 1.0),
+            Delay::PING_PONG => self.ping_pong = value >= 0.5,
+            Delay::WET => self.wet.target = value.max(0.0),
+            Delay::DRY => self.dry.target = value.max(0.0),
+            _ => {}
+        }
+    }
+}
+
+fn ms_to_frames(ms: f32) -> f32 {
+    ms.max(0.0) / 1000.0 * SAMPLE_RATE as f32
+}
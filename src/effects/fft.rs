@@ -0,0 +1,173 @@
+//! Minimal radix-2 complex FFT, just enough for the convolution reverb.
+
+use std::ops::{Add, Mul, Sub};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub(crate) struct Complex {
+    pub re: f32,
+    pub im: f32,
+}
+
+impl Complex {
+    pub fn new(re: f32, im: f32) -> Complex {
+        Complex { re, im }
+    }
+}
+
+impl Add for Complex {
+    type Output = Complex;
+
+    fn add(self, other: Complex) -> Complex {
+        Complex::new(self.re + other.re, self.im + other.im)
+    }
+}
+
+impl Sub for Complex {
+    type Output = Complex;
+
+    fn sub(self, other: Complex) -> Complex {
+        Complex::new(self.re - other.re, self.im - other.im)
+    }
+}
+
+impl Mul for Complex {
+    type Output = Complex;
+
+    fn mul(self, other: Complex) -> Complex {
+        Complex::new(
+            self.re * other.re - self.im * other.im,
+            self.re * other.im + self.im * other.re,
+        )
+    }
+}
+
+/// Precomputed tables for in-place FFTs of one power of two size.
+#[derive(Debug, Clone)]
+pub(crate) struct Fft {
+    /// `exp(-2πik/size)` for `k` in `0..size / 2`.
+    twiddles: Vec<Complex>,
+    bit_reversed: Vec<usize>,
+}
+
+impl Fft {
+    pub fn new(size: usize) -> Fft {
+        assert!(size.is_power_of_two(), "FFT size should be a power of two");
+
+        let bits = size.trailing_zeros();
+        let twiddles = (0..size / 2)
+            .map(|k| {
+                let angle = -2.0 * std::f64::consts::PI * k as f64 / size as f64;
+                Complex::new(angle.cos() as f32, angle.sin() as f32)
+            })
+            .collect();
+        let bit_reversed = (0..size)
+            .map(|i| {
+                i.reverse_bits()
+                    .checked_shr(usize::BITS - bits)
+                    .unwrap_or(0)
+            })
+            .collect();
+
+        Fft {
+            twiddles,
+            bit_reversed,
+        }
+    }
+
+    pub fn size(&self) -> usize {
+        self.bit_reversed.len()
+    }
+
+    pub fn forward(&self, data: &mut [Complex]) {
+        self.transform(data, false);
+    }
+
+    /// Inverse transform, scaled so that `inverse(forward(x)) == x`.
+    pub fn inverse(&self, data: &mut [Complex]) {
+        self.transform(data, true);
+
+        let scale = 1.0 / data.len() as f32;
+        for value in data {
+            value.re *= scale;
+            value.im *= scale;
+        }
+    }
+
+    fn transform(&self, data: &mut [Complex], inverse: bool) {
+        let size = self.size();
+        assert_eq!(data.len(), size);
+
+        for (i, &j) in self.bit_reversed.iter().enumerate() {
+            if i < j {
+                data.swap(i, j);
+            }
+        }
+
+        let mut len = 2;
+        while len <= size {
+            let stride = size / len;
+
+            for chunk in data.chunks_exact_mut(len) {
+                let (even, odd) = chunk.split_at_mut(len / 2);
+
+                for (k, (even, odd)) in even.iter_mut().zip(odd.iter_mut()).enumerate() {
+                    let mut twiddle = self.twiddles[k * stride];
+                    if inverse {
+                        twiddle.im = -twiddle.im;
+                    }
+
+                    let product = *odd * twiddle;
+                    *odd = *even - product;
+                    *even = *even + product;
+                }
+            }
+
+            len *= 2;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn signal(size: usize) -> Vec<Complex> {
+        (0..size)
+            .map(|i| Complex::new((i as f32 * 0.37).sin(), (i as f32 * 0.11).cos() * 0.5))
+            .collect()
+    }
+
+    #[test]
+    fn round_trip() {
+        let fft = Fft::new(1024);
+        let input = signal(1024);
+
+        let mut data = input.clone();
+        fft.forward(&mut data);
+        fft.inverse(&mut data);
+
+        for (a, b) in data.iter().zip(&input) {
+            assert!((a.re - b.re).abs() < 1e-4 && (a.im - b.im).abs() < 1e-4);
+        }
+    }
+
+    #[test]
+    fn matches_direct_dft() {
+        let size = 64;
+        let fft = Fft::new(size);
+        let input = signal(size);
+
+        let mut data = input.clone();
+        fft.forward(&mut data);
+
+        for (k, bin) in data.iter().enumerate() {
+            let mut expected = Complex::default();
+            for (n, &x) in input.iter().enumerate() {
+                let angle = -2.0 * std::f32::consts::PI * (k * n % size) as f32 / size as f32;
+                expected = expected + x * Complex::new(angle.cos(), angle.sin());
+            }
+
+            assert!((bin.re - expected.re).abs() < 1e-3 && (bin.im - expected.im).abs() < 1e-3);
+        }
+    }
+}
@@ -0,0 +1,203 @@
+//! Audio effects, processing playbacks and buses on the audio thread.
+//!
+//! Any `AudioEffect` can be inserted with `Bus::add_effect` or `Playback::add_effect`
+//! and then controlled through the returned `Effect` handle.
+
+use crate::{
+    ramp::{Ramp, DECLICK_FRAMES},
+    FadeCurve, SAMPLE_RATE,
+};
+
+use std::any::Any;
+
+mod biquad;
+mod compressor;
+mod convolver;
+mod delay;
+mod fft;
+mod reverb;
+
+pub use biquad::{BiquadFilter, FilterType};
+pub use compressor::{Compressor, CompressorSettings, Detection};
+pub use convolver::{Convolver, ImpulseResponse};
+pub use delay::{Delay, DelaySettings, DelayTime};
+pub use reverb::{Reverb, ReverbSettings};
+
+/// Effect parameters are smoothed over roughly this many seconds.
+const SMOOTHING_TIME: f32 = 0.01;
+
+/// While parameters are moving, effects recompute their internal state every this many frames.
+const UPDATE_FRAMES: usize = 16;
+
+/// Audio processor, running on the audio thread.
+pub trait AudioEffect: Send {
+    /// Process a block of interleaved stereo frames in place.
+    fn process(&mut self, buffer: &mut [f32]);
+
+    /// Forget all the internal state, like delay lines or envelopes,
+    /// as if the effect was just created.
+    fn reset(&mut self);
+
+    /// Change a parameter, `Effect::set_parameter` ends up here.
+    /// Meaning of `index` is up to the effect.
+    fn set_parameter(&mut self, _index: u32, _value: f32) {}
+
+    /// Receive data that does not fit into a parameter, sent with `Effect::set_data`.
+    /// Expected type is up to the effect, anything else should be returned untouched.
+    ///
+    /// The returned box, usually the data being replaced, is dropped on the control side:
+    /// freeing memory on the audio thread may block it.
+    fn set_data(&mut self, data: Box<dyn Any + Send>) -> Option<Box<dyn Any + Send>> {
+        Some(data)
+    }
+
+    /// Delay introduced by the effect, in frames.
+    /// The dry signal of the effect's wet/dry mix is not delayed.
+    fn latency(&self) -> usize {
+        0
+    }
+}
+
+/// A parameter moving exponentially towards its target.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Smoothed {
+    pub value: f32,
+    pub target: f32,
+}
+
+impl Smoothed {
+    pub fn new(value: f32) -> Smoothed {
+        Smoothed {
+            value,
+            target: value,
+        }
+    }
+
+    /// Advance by `frames` frames, returns false if the value did not change.
+    pub fn advance(&mut self, frames: usize) -> bool {
+        self.advance_over(frames, SMOOTHING_TIME)
+    }
+
+    /// Same as `advance`, with a custom smoothing time, in seconds.
+    pub fn advance_over(&mut self, frames: usize, time: f32) -> bool {
+        if self.value == self.target {
+            return false;
+        }
+
+        let coef = (-(frames as f32) / (time * SAMPLE_RATE as f32)).exp();
+        self.value = self.target + (self.value - self.target) * coef;
+
+        if (self.value - self.target).abs() <= self.target.abs().max(1.0) * 1e-4 {
+            self.value = self.target;
+        }
+
+        true
+    }
+}
+
+/// An effect in a chain, with its wet/dry mix and bypass.
+pub(crate) struct EffectSlot {
+    id: u32,
+    effect: Box<dyn AudioEffect>,
+    mix: f32,
+    bypass: bool,
+    /// `mix`, or 0.0 while bypassed.
+    wet: Ramp,
+    dry: Vec<f32>,
+}
+
+impl EffectSlot {
+    pub fn set_parameter(&mut self, index: u32, value: f32) {
+        self.effect.set_parameter(index, value);
+    }
+
+    pub fn set_data(&mut self, data: Box<dyn Any + Send>) -> Option<Box<dyn Any + Send>> {
+        self.effect.set_data(data)
+    }
+
+    pub fn set_mix(&mut self, mix: f32) {
+        self.mix = mix.clamp(0.0, 1.0);
+        self.update_wet();
+    }
+
+    pub fn set_bypass(&mut self, bypass: bool) {
+        self.bypass = bypass;
+        self.update_wet();
+    }
+
+    pub fn reset(&mut self) {
+        self.effect.reset();
+    }
+
+    fn update_wet(&mut self) {
+        let wet = if self.bypass { 0.0 } else { self.mix };
+
+        self.wet.start(wet, DECLICK_FRAMES, FadeCurve::Linear);
+    }
+
+    fn process(&mut self, buffer: &mut [f32]) {
+        // fully bypassed effects cost nothing
+        if self.bypass && self.wet.is_finished() {
+            return;
+        }
+
+        self.dry.clear();
+        self.dry.extend_from_slice(buffer);
+
+        self.effect.process(buffer);
+
+        for (wet, dry) in buffer.chunks_exact_mut(2).zip(self.dry.chunks_exact(2)) {
+            let mix = self.wet.next();
+
+            wet[0] = dry[0] + (wet[0] - dry[0]) * mix;
+            wet[1] = dry[1] + (wet[1] - dry[1]) * mix;
+        }
+    }
+}
+
+/// Effects, applied one after another.
+#[derive(Default)]
+pub(crate) struct EffectChain {
+    slots: Vec<EffectSlot>,
+}
+
+impl EffectChain {
+    pub fn push(&mut self, id: u32, effect: Box<dyn AudioEffect>) {
+        self.slots.push(EffectSlot {
+            id,
+            effect,
+            mix: 1.0,
+            bypass: false,
+            wet: Ramp::new(1.0),
+            dry: vec![],
+        });
+    }
+
+    pub fn remove(&mut self, id: u32) -> Option<EffectSlot> {
+        let index = self.slots.iter().position(|slot| slot.id == id)?;
+
+        Some(self.slots.remove(index))
+    }
+
+    pub fn get_mut(&mut self, id: u32) -> Option<&mut EffectSlot> {
+        self.slots.iter_mut().find(|slot| slot.id == id)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.slots.is_empty()
+    }
+
+    pub fn process(&mut self, buffer: &mut [f32]) {
+        for slot in &mut self.slots {
+            slot.process(buffer);
+        }
+    }
+}
+
+impl std::fmt::Debug for EffectChain {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_list()
+            .entries(self.slots.iter().map(|slot| slot.id))
+            .finish()
+    }
+}
@@ -0,0 +1,337 @@
+//! Freeverb, Schroeder/Moorer reverb by Jezar at Dreampoint.
+//! https://ccrma.stanford.edu/~jos/pasp/Freeverb.html
+
+use super::{AudioEffect, Smoothed, UPDATE_FRAMES};
+use crate::SAMPLE_RATE;
+
+/// Delay line lengths, in frames at 44100 Hz.
+const COMB_TUNING: [usize; 8] = [1116, 1188, 1277, 1356, 1422, 1491, 1557, 1617];
+const ALLPASS_TUNING: [usize; 4] = [556, 441, 341, 225];
+/// Right channel delay lines are this much longer, to decorrelate the channels.
+const STEREO_SPREAD: usize = 23;
+
+const FIXED_GAIN: f32 = 0.015;
+const SCALE_WET: f32 = 3.0;
+const SCALE_DAMPING: f32 = 0.4;
+const SCALE_ROOM: f32 = 0.28;
+const OFFSET_ROOM: f32 = 0.7;
+const ALLPASS_FEEDBACK: f32 = 0.5;
+
+/// Longest supported pre-delay, in seconds.
+const MAX_PRE_DELAY: f32 = 0.5;
+
+/// `Reverb` parameters, all of them can be changed later with `Effect::set_parameter`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ReverbSettings {
+    /// From 0.0 (small room, short tail) to 1.0 (huge hall).
+    pub room_size: f32,
+    /// High frequency absorption, from 0.0 (bright, reflective walls) to 1.0 (dark, soft walls).
+    pub damping: f32,
+    /// Time before the reverb starts, in seconds, up to 0.5.
+    pub pre_delay: f32,
+    /// Stereo width of the reverb tail, from 0.0 (mono) to 1.0.
+    pub width: f32,
+    /// Level of the reverberated signal.
+    pub wet: f32,
+    /// Level of the unprocessed signal. Use 0.0 when the reverb is on an aux send bus.
+    pub dry: f32,
+}
+
+impl Default for ReverbSettings {
+    fn default() -> ReverbSettings {
+        ReverbSettings {
+            room_size: 0.5,
+            damping: 0.5,
+            pre_delay: 0.02,
+            width: 1.0,
+            wet: 0.33,
+            dry: 1.0,
+        }
+    }
+}
+
+impl ReverbSettings {
+    /// Short and bright, for aux send buses.
+    pub fn small_room() -> ReverbSettings {
+        ReverbSettings {
+            room_size: 0.3,
+            damping: 0.3,
+            pre_delay: 0.005,
+            width: 0.7,
+            wet: 0.8,
+            dry: 0.0,
+        }
+    }
+
+    /// Long and smooth, for aux send buses.
+    pub fn hall() -> ReverbSettings {
+        ReverbSettings {
+            room_size: 0.85,
+            damping: 0.5,
+            pre_delay: 0.03,
+            width: 1.0,
+            wet: 1.0,
+            dry: 0.0,
+        }
+    }
+
+    /// Very long and dark, for aux send buses.
+    pub fn cave() -> ReverbSettings {
+        ReverbSettings {
+            room_size: 0.95,
+            damping: 0.7,
+            pre_delay: 0.06,
+            width: 1.0,
+            wet: 1.2,
+            dry: 0.0,
+        }
+    }
+
+    /// Almost no reverb, for aux send buses.
+    pub fn outdoors() -> ReverbSettings {
+        ReverbSettings {
+            room_size: 0.1,
+            damping: 0.8,
+            pre_delay: 0.0,
+            width: 1.0,
+            wet: 0.1,
+            dry: 0.0,
+        }
+    }
+
+    /// Pairs of `Reverb` parameter indices and values.
+    pub(crate) fn parameters(&self) -> [(u32, f32); 6] {
+        [
+            (Reverb::ROOM_SIZE, self.room_size),
+            (Reverb::DAMPING, self.damping),
+            (Reverb::PRE_DELAY, self.pre_delay),
+            (Reverb::WIDTH, self.width),
+            (Reverb::WET, self.wet),
+            (Reverb::DRY, self.dry),
+        ]
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Comb {
+    buffer: Vec<f32>,
+    index: usize,
+    filter_store: f32,
+}
+
+impl Comb {
+    fn new(len: usize) -> Comb {
+        Comb {
+            buffer: vec![0.0; len],
+            index: 0,
+            filter_store: 0.0,
+        }
+    }
+
+    fn process(&mut self, input: f32, feedback: f32, damping: f32) -> f32 {
+        let output = self.buffer[self.index];
+
+        self.filter_store = output * (1.0 - damping) + self.filter_store * damping;
+        // keep decaying tails out of the very slow denormal range
+        if self.filter_store.abs() < 1e-20 {
+            self.filter_store = 0.0;
+        }
+
+        self.buffer[self.index] = input + self.filter_store * feedback;
+        self.index = (self.index + 1) % self.buffer.len();
+
+        output
+    }
+
+    fn reset(&mut self) {
+        self.buffer.iter_mut().for_each(|sample| *sample = 0.0);
+        self.filter_store = 0.0;
+    }
+}
+
+#[derive(Debug, Clone)]
+struct Allpass {
+    buffer: Vec<f32>,
+    index: usize,
+}
+
+impl Allpass {
+    fn new(len: usize) -> Allpass {
+        Allpass {
+            buffer: vec![0.0; len],
+            index: 0,
+        }
+    }
+
+    fn process(&mut self, input: f32) -> f32 {
+        let delayed = self.buffer[self.index];
+
+        self.buffer[self.index] = input + delayed * ALLPASS_FEEDBACK;
+        self.index = (self.index + 1) % self.buffer.len();
+
+        delayed - input
+    }
+
+    fn reset(&mut self) {
+        self.buffer.iter_mut().for_each(|sample| *sample = 0.0);
+    }
+}
+
+/// Stereo algorithmic reverb.
+///
+/// Works as an insert effect with some `dry` signal, or on an aux bus
+/// fed by `PlaySoundParams::sends` with `dry` set to 0.0.
+#[derive(Debug, Clone)]
+pub struct Reverb {
+    room_size: Smoothed,
+    damping: Smoothed,
+    width: Smoothed,
+    wet: Smoothed,
+    dry: Smoothed,
+    /// Pre-delay, in frames, fractional while it glides to a new value.
+    pre_delay: Smoothed,
+    /// Mono reverb input, delayed by `pre_delay`.
+    pre_delay_line: Vec<f32>,
+    pre_delay_index: usize,
+    combs: [Vec<Comb>; 2],
+    allpasses: [Vec<Allpass>; 2],
+    /// Frames left until the next parameters update.
+    countdown: usize,
+}
+
+impl Reverb {
+    pub const ROOM_SIZE: u32 = 0;
+    pub const DAMPING: u32 = 1;
+    /// Pre-delay, in seconds.
+    pub const PRE_DELAY: u32 = 2;
+    pub const WIDTH: u32 = 3;
+    pub const WET: u32 = 4;
+    pub const DRY: u32 = 5;
+
+    pub fn new(settings: ReverbSettings) -> Reverb {
+        let scale = SAMPLE_RATE as f32 / 44100.0;
+        let delay = |len: usize, channel: usize| {
+            (((len + channel * STEREO_SPREAD) as f32 * scale) as usize).max(1)
+        };
+
+        let mut reverb = Reverb {
+            room_size: Smoothed::new(settings.room_size.clamp(0.0, 1.0)),
+            damping: Smoothed::new(settings.damping.clamp(0.0, 1.0)),
+            width: Smoothed::new(settings.width.clamp(0.0, 1.0)),
+            wet: Smoothed::new(settings.wet.max(0.0)),
+            dry: Smoothed::new(settings.dry.max(0.0)),
+            pre_delay: Smoothed::new(0.0),
+            pre_delay_line: vec![0.0; (MAX_PRE_DELAY * SAMPLE_RATE as f32) as usize + 2],
+            pre_delay_index: 0,
+            combs: [0, 1].map(|channel| {
+                COMB_TUNING
+                    .iter()
+                    .map(|&len| Comb::new(delay(len, channel)))
+                    .collect()
+            }),
+            allpasses: [0, 1].map(|channel| {
+                ALLPASS_TUNING
+                    .iter()
+                    .map(|&len| Allpass::new(delay(len, channel)))
+                    .collect()
+            }),
+            countdown: 0,
+        };
+        reverb.pre_delay = Smoothed::new(reverb.pre_delay_frames(settings.pre_delay));
+
+        reverb
+    }
+
+    fn pre_delay_frames(&self, pre_delay: f32) -> f32 {
+        let frames = pre_delay.clamp(0.0, MAX_PRE_DELAY) * SAMPLE_RATE as f32;
+
+        frames.min((self.pre_delay_line.len() - 2) as f32)
+    }
+}
+
+impl Default for Reverb {
+    fn default() -> Reverb {
+        Reverb::new(ReverbSettings::default())
+    }
+}
+
+impl AudioEffect for Reverb {
+    fn process(&mut self, buffer: &mut [f32]) {
+        let mut feedback = self.room_size.value * SCALE_ROOM + OFFSET_ROOM;
+        let mut damping = self.damping.value * SCALE_DAMPING;
+
+        for frame in buffer.chunks_exact_mut(2) {
+            if self.countdown == 0 {
+                self.countdown = UPDATE_FRAMES;
+
+                for parameter in [
+                    &mut self.room_size,
+                    &mut self.damping,
+                    &mut self.width,
+                    &mut self.wet,
+                    &mut self.dry,
+                ] {
+                    parameter.advance(UPDATE_FRAMES);
+                }
+                feedback = self.room_size.value * SCALE_ROOM + OFFSET_ROOM;
+                damping = self.damping.value * SCALE_DAMPING;
+            }
+            self.countdown -= 1;
+
+            // pre-delay is smoothed per frame and read with interpolation, steps would click
+            self.pre_delay.advance(1);
+
+            let len = self.pre_delay_line.len();
+            self.pre_delay_line[self.pre_delay_index] = (frame[0] + frame[1]) * FIXED_GAIN;
+            let position = (self.pre_delay_index + len) as f32 - self.pre_delay.value;
+            let index = position as usize;
+            let t = position - index as f32;
+            let a = self.pre_delay_line[index % len];
+            let b = self.pre_delay_line[(index + 1) % len];
+            let input = a + (b - a) * t;
+            self.pre_delay_index = (self.pre_delay_index + 1) % len;
+
+            let mut out = [0.0; 2];
+            for (out, (combs, allpasses)) in out
+                .iter_mut()
+                .zip(self.combs.iter_mut().zip(&mut self.allpasses))
+            {
+                *out = combs
+                    .iter_mut()
+                    .map(|comb| comb.process(input, feedback, damping))
+                    .sum();
+                for allpass in allpasses.iter_mut() {
+                    *out = allpass.process(*out);
+                }
+            }
+
+            let wet = self.wet.value * SCALE_WET;
+            let wet1 = wet * (self.width.value / 2.0 + 0.5);
+            let wet2 = wet * ((1.0 - self.width.value) / 2.0);
+            let dry = self.dry.value;
+
+            frame[0] = out[0] * wet1 + out[1] * wet2 + frame[0] * dry;
+            frame[1] = out[1] * wet1 + out[0] * wet2 + frame[1] * dry;
+        }
+    }
+
+    fn reset(&mut self) {
+        self.pre_delay_line
+            .iter_mut()
+            .for_each(|sample| *sample = 0.0);
+        self.combs.iter_mut().flatten().for_each(Comb::reset);
+        self.allpasses.iter_mut().flatten().for_each(Allpass::reset);
+    }
+
+    fn set_parameter(&mut self, index: u32, value: f32) {
+        match index {
+            Reverb::ROOM_SIZE => self.room_size.target = value.clamp(0.0, 1.0),
+            Reverb::DAMPING => self.damping.target = value.clamp(0.0, 1.0),
+            Reverb::PRE_DELAY => self.pre_delay.target = self.pre_delay_frames(value),
+            Reverb::WIDTH => self.width.target = value.clamp(0.0, 1.0),
+            Reverb::WET => self.wet.target = value.max(0.0),
+            Reverb::DRY => self.dry.target = value.max(0.0),
+            _ => {}
+        }
+    }
+}
@@ -0,0 +1,356 @@
+//! Binaural rendering with head related transfer functions.
+
+#[cfg(not(target_arch = "wasm32"))]
+use crate::mixer::load_samples_from_file;
+use crate::{spatial::dot, SAMPLE_RATE};
+
+use std::f32::consts::PI;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Source of `HrtfSet::generation` values.
+static GENERATION: AtomicUsize = AtomicUsize::new(0);
+
+/// Impulse responses are cut to this many frames.
+const MAX_HRIR_FRAMES: usize = 256;
+
+/// Frames in the impulse responses of `HrtfSet::spherical_head`.
+const MODEL_FRAMES: usize = 128;
+/// Average head radius, in meters.
+const HEAD_RADIUS: f32 = 0.0875;
+const SPEED_OF_SOUND: f32 = 343.0;
+
+/// Pinna echoes of the spherical head model: reflection coefficient, and delay
+/// parameters A, B, D of `A * cos(azimuth / 2) * sin(D * (90° - elevation)) + B`, in frames.
+/// Brown and Duda, "A Structural Model for Binaural Sound Synthesis", 1998.
+const PINNA_ECHOES: [(f32, f32, f32, f32); 5] = [
+    (0.5, 1.0, 2.0, 0.85),
+    (-1.0, 5.0, 4.0, 0.35),
+    (0.5, 5.0, 7.0, 0.35),
+    (-0.25, 5.0, 11.0, 0.35),
+    (0.25, 5.0, 13.0, 0.35),
+];
+
+/// Left and right ear impulse responses for one direction.
+#[derive(Debug, Clone)]
+struct Hrir {
+    /// Unit vector towards the source, in listener space: x right, y up, z backwards.
+    direction: [f32; 3],
+    /// Impulse responses, time reversed, so convolution is a dot product with the input history.
+    reversed: [Vec<f32>; 2],
+}
+
+/// A set of head related impulse responses, measured from many directions around the head.
+///
+/// No measured data ships with the crate. The default set, `HrtfSet::spherical_head`,
+/// is synthetic: the Brown and Duda spherical head model, computed on startup.
+/// It gives solid left/right cues but weak front/back and elevation ones.
+/// For better cues, load a measured set, for example MIT KEMAR or a SOFA dataset
+/// exported to .wav files, and install it with `AudioContext::set_hrtf`.
+#[derive(Debug, Clone)]
+pub struct HrtfSet {
+    hrirs: Vec<Hrir>,
+    /// Length of every impulse response, in frames.
+    frames: usize,
+    /// Changes on every modification, so panners can tell their measurement indices are stale.
+    generation: usize,
+}
+
+impl HrtfSet {
+    /// An empty set, to be filled with `add_measurement` or `load_measurement`.
+    pub fn new() -> HrtfSet {
+        HrtfSet {
+            hrirs: vec![],
+            frames: 1,
+            generation: GENERATION.fetch_add(1, Ordering::Relaxed),
+        }
+    }
+
+    /// Synthetic set from the Brown and Duda spherical head model:
+    /// interaural time and level differences plus simple pinna echoes.
+    /// Small, needs no data files and works for everyone equally well, which is to say
+    /// not as well as a measured set.
+    pub fn spherical_head() -> HrtfSet {
+        let mut set = HrtfSet::new();
+
+        for elevation in (-40..=90).step_by(10) {
+            // fewer azimuths closer to the pole
+            let count = ((36.0 * (elevation as f32).to_radians().cos()).round() as usize).max(1);
+
+            for i in 0..count {
+                let azimuth = 360.0 * i as f32 / count as f32;
+                let samples = spherical_head_hrir(azimuth, elevation as f32);
+
+                set.add_measurement(azimuth, elevation as f32, &samples);
+            }
+        }
+
+        set
+    }
+
+    /// Add an impulse response for a direction. `azimuth` is in degrees clockwise
+    /// from the front (90 is right), `elevation` in degrees above the horizon.
+    /// `samples` are interleaved left/right ear frames at the mixer sample rate.
+    pub fn add_measurement(&mut self, azimuth: f32, elevation: f32, samples: &[f32]) {
+        let (azimuth, elevation) = (azimuth.to_radians(), elevation.to_radians());
+        let direction = [
+            azimuth.sin() * elevation.cos(),
+            elevation.sin(),
+            -azimuth.cos() * elevation.cos(),
+        ];
+
+        let frames = (samples.len() / 2).min(MAX_HRIR_FRAMES);
+        let reversed = [0, 1].map(|ear| {
+            samples
+                .chunks_exact(2)
+                .take(frames)
+                .map(|frame| frame[ear])
+                .rev()
+                .collect::<Vec<f32>>()
+        });
+
+        self.hrirs.push(Hrir {
+            direction,
+            reversed,
+        });
+
+        // all the responses are zero padded to the same length
+        self.frames = self.frames.max(frames);
+        self.generation = GENERATION.fetch_add(1, Ordering::Relaxed);
+        for hrir in &mut self.hrirs {
+            for reversed in &mut hrir.reversed {
+                let padding = self.frames - reversed.len();
+                reversed.splice(0..0, std::iter::repeat(0.0).take(padding));
+            }
+        }
+    }
+
+    /// Decode a stereo .wav or .ogg impulse response, the same way as `Sound::load` does,
+    /// and add it for a direction.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn load_measurement(&mut self, azimuth: f32, elevation: f32, data: &[u8]) {
+        let samples = load_samples_from_file(data).unwrap();
+
+        self.add_measurement(azimuth, elevation, &samples);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.hrirs.is_empty()
+    }
+
+    /// Length of every impulse response, in frames.
+    pub(crate) fn frames(&self) -> usize {
+        self.frames
+    }
+
+    /// Time reversed left and right ear responses measured closest to `direction` in listener space.
+    pub(crate) fn reversed_response(&self, direction: [f32; 3]) -> Option<&[Vec<f32>; 2]> {
+        if self.is_empty() {
+            return None;
+        }
+
+        Some(&self.hrirs[self.nearest(direction)].reversed)
+    }
+
+    /// Index of the measurement closest to the `direction` in listener space.
+    fn nearest(&self, direction: [f32; 3]) -> usize {
+        let mut nearest = 0;
+        let mut best = f32::MIN;
+
+        for (i, hrir) in self.hrirs.iter().enumerate() {
+            let similarity = dot(hrir.direction, direction);
+            if similarity > best {
+                best = similarity;
+                nearest = i;
+            }
+        }
+
+        nearest
+    }
+}
+
+impl Default for HrtfSet {
+    fn default() -> HrtfSet {
+        HrtfSet::spherical_head()
+    }
+}
+
+/// Left and right ear responses of the spherical head model, interleaved.
+fn spherical_head_hrir(azimuth: f32, elevation: f32) -> Vec<f32> {
+    let (azimuth, elevation) = (azimuth.to_radians(), elevation.to_radians());
+    let direction = [
+        azimuth.sin() * elevation.cos(),
+        elevation.sin(),
+        -azimuth.cos() * elevation.cos(),
+    ];
+    let sample_rate = SAMPLE_RATE as f32;
+    let w0 = SPEED_OF_SOUND / HEAD_RADIUS;
+    let k = 2.0 * sample_rate;
+
+    let mut samples = vec![0.0; MODEL_FRAMES * 2];
+
+    for (ear, side) in [(0, -1.0f32), (1, 1.0)] {
+        // angle between the source and the ear axis
+        let incidence = (direction[0] * side).clamp(-1.0, 1.0).acos();
+
+        // head shadow, one pole one zero filter: boost facing the source, low-pass behind the head
+        let alpha = 1.05 + 0.95 * (incidence / 150.0f32.to_radians() * PI).cos();
+        let b0 = (2.0 * w0 + alpha * k) / (2.0 * w0 + k);
+        let b1 = (2.0 * w0 - alpha * k) / (2.0 * w0 + k);
+        let a1 = (2.0 * w0 - k) / (2.0 * w0 + k);
+
+        // interaural time difference, Woodworth's formula, made non-negative
+        let delay = if incidence < PI / 2.0 {
+            -incidence.cos()
+        } else {
+            incidence - PI / 2.0
+        };
+        let delay = (delay + 1.0) * HEAD_RADIUS / SPEED_OF_SOUND * sample_rate;
+
+        // azimuth as seen from this ear, 0.0 facing it
+        let ear_azimuth = if side > 0.0 { azimuth } else { -azimuth };
+        let ear_azimuth = (ear_azimuth + PI).rem_euclid(2.0 * PI) - PI;
+
+        let mut impulse = vec![0.0; MODEL_FRAMES];
+        let mut add_tap = |position: f32, gain: f32| {
+            let index = position as usize;
+            let t = position - index as f32;
+
+            if index + 1 < MODEL_FRAMES {
+                impulse[index] += gain * (1.0 - t);
+                impulse[index + 1] += gain * t;
+            }
+        };
+        add_tap(delay, 1.0);
+        for &(reflection, a, b, d) in &PINNA_ECHOES {
+            let echo = a * (ear_azimuth / 2.0).cos() * (d * (PI / 2.0 - elevation)).sin() + b;
+            add_tap(delay + echo.max(0.0), reflection * 0.5);
+        }
+
+        let (mut input, mut output) = (0.0, 0.0);
+        for (i, &sample) in impulse.iter().enumerate() {
+            output = b0 * sample + b1 * input - a1 * output;
+            input = sample;
+            samples[i * 2 + ear] = output;
+        }
+    }
+
+    samples
+}
+
+/// Per playback binaural renderer: mono input convolved with the nearest HRIR.
+#[derive(Debug)]
+pub(crate) struct HrtfPanner {
+    /// Mono input history, stored twice to always have `frames` contiguous samples.
+    history: Vec<f32>,
+    position: usize,
+    /// `HrtfSet::generation` of the set `current` and `previous` index into.
+    generation: Option<usize>,
+    current: Option<usize>,
+    /// Measurement being crossfaded from, during the buffer after a direction change.
+    previous: Option<usize>,
+}
+
+impl HrtfPanner {
+    pub fn new() -> HrtfPanner {
+        HrtfPanner {
+            history: vec![],
+            position: 0,
+            generation: None,
+            current: None,
+            previous: None,
+        }
+    }
+
+    /// Render interleaved stereo `buffer` in place for a source at `direction`
+    /// in listener space.
+    pub fn process(&mut self, set: &HrtfSet, direction: [f32; 3], buffer: &mut [f32]) {
+        if set.is_empty() {
+            return;
+        }
+
+        let frames = set.frames;
+        if self.generation != Some(set.generation) {
+            // a new set was installed, old measurement indices mean nothing
+            self.generation = Some(set.generation);
+            self.current = None;
+            self.previous = None;
+
+            if self.history.len() != frames * 2 {
+                self.history = vec![0.0; frames * 2];
+                self.position = 0;
+            }
+        }
+
+        let nearest = set.nearest(direction);
+        if self.current != Some(nearest) {
+            self.previous = self.current;
+            self.current = Some(nearest);
+        }
+        let current = &set.hrirs[nearest];
+        let previous = self.previous.map(|previous| &set.hrirs[previous]);
+
+        let len = buffer.len() / 2;
+        for (n, frame) in buffer.chunks_exact_mut(2).enumerate() {
+            self.position = (self.position + 1) % frames;
+            let mono = (frame[0] + frame[1]) * 0.5;
+            self.history[self.position] = mono;
+            self.history[self.position + frames] = mono;
+
+            let window = &self.history[self.position + 1..self.position + 1 + frames];
+            let convolve = |hrir: &Hrir, ear: usize| -> f32 {
+                window
+                    .iter()
+                    .zip(&hrir.reversed[ear])
+                    .map(|(x, h)| x * h)
+                    .sum()
+            };
+
+            for ear in 0..2 {
+                frame[ear] = convolve(current, ear);
+            }
+
+            if let Some(previous) = previous {
+                let t = (n + 1) as f32 / len as f32;
+
+                for ear in 0..2 {
+                    let old = convolve(previous, ear);
+                    frame[ear] = old + (frame[ear] - old) * t;
+                }
+            }
+        }
+
+        self.previous = None;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn swap_to_smaller_set_with_same_length() {
+        let mut panner = HrtfPanner::new();
+        let mut buffer = vec![0.5; 512];
+
+        let spherical_head = HrtfSet::spherical_head();
+        panner.process(&spherical_head, [0.0, 0.0, 1.0], &mut buffer);
+
+        // same impulse response length, far fewer measurements
+        let mut set = HrtfSet::new();
+        let mut impulse = vec![0.0; MODEL_FRAMES * 2];
+        impulse[0] = 1.0;
+        impulse[1] = 0.5;
+        set.add_measurement(-90.0, 0.0, &impulse);
+        set.add_measurement(90.0, 0.0, &impulse);
+        assert_eq!(set.frames, spherical_head.frames);
+
+        let mut buffer = vec![0.5; 512];
+        panner.process(&set, [0.0, 0.0, -1.0], &mut buffer);
+
+        assert!(buffer.iter().all(|sample| sample.is_finite()));
+        // after the history filled up, output is the impulse response applied to the input
+        let last = &buffer[buffer.len() - 2..];
+        assert!((last[0] - 0.5).abs() < 1e-6);
+        assert!((last[1] - 0.25).abs() < 1e-6);
+    }
+}
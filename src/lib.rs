@@ -29,11 +29,108 @@ mod snd;
 #[cfg(not(target_arch = "wasm32"))]
 mod mixer;
 
-pub use snd::{AudioContext, Playback, Sound};
+#[cfg(not(target_arch = "wasm32"))]
+mod offline;
+
+mod ambisonic;
+mod ducking;
+mod hrtf;
+mod limiter;
+mod ramp;
+mod spatial;
+mod zones;
+
+// effects exist on every platform, the web backend does not run them
+pub mod effects;
+
+pub use snd::{AudioContext, Bus, Playback, Sound};
+
+pub use ambisonic::AmbisonicFormat;
+pub use ducking::Ducking;
+pub use hrtf::HrtfSet;
+#[cfg(not(target_arch = "wasm32"))]
+pub use mixer::Effect;
+#[cfg(not(target_arch = "wasm32"))]
+pub use offline::{write_wav, OfflineContext};
+#[cfg(target_arch = "wasm32")]
+pub use snd::Effect;
+pub use spatial::{Cone, DistanceModel, Emitter, Listener, ListenerMix, Panning};
+pub use zones::{ReverbZone, ZoneShape};
+
+/// All the sounds are resampled to this rate on load and mixed at it.
+pub(crate) const SAMPLE_RATE: u32 = 44100;
 
 pub struct PlaySoundParams {
     pub looped: bool,
     pub volume: f32,
+    /// Position in the sound to start playing from, in seconds.
+    pub start_offset: f32,
+    /// Playback speed. 1.0 is the original pitch, 2.0 is twice as fast and an octave higher.
+    pub pitch: f32,
+    /// Stereo position, from -1.0 (left) through 0.0 (center) to 1.0 (right).
+    pub pan: f32,
+    pub pan_law: PanLaw,
+    /// Time to ramp volume up from silence, in seconds.
+    pub fade_in: f32,
+    pub fade_in_curve: FadeCurve,
+    pub bus: Bus,
+    /// Aux sends: the playback signal, after its volume and pan, is also mixed
+    /// into each of these buses at the given level. For example into a bus with a reverb.
+    pub sends: Vec<(Bus, f32)>,
+    /// Position in 3D space, `None` for a plain stereo playback.
+    /// Ignored on web, where playbacks are not spatialized.
+    pub emitter: Option<Emitter>,
+}
+
+/// Shape of a volume ramp over time.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FadeCurve {
+    Linear,
+    /// Constant change in decibels per second, sounds the most natural for long fades.
+    Exponential,
+    /// Starts and ends slowly, moving fastest in the middle.
+    SCurve,
+    /// Quarter of a sine wave. A fade-in and a fade-out of the same length
+    /// keep the total power constant, for crossfades.
+    EqualPower,
+}
+
+/// Master output limiter, keeps loud mixes from clipping.
+/// Set with `AudioContext::set_limiter`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Limiter {
+    /// Output peaks are kept at or below this level, in dBFS.
+    pub ceiling: f32,
+    /// How early gain reduction starts before a peak, in seconds.
+    /// The whole output is delayed by this amount.
+    pub lookahead: f32,
+    /// Time for the gain to recover after a peak, in seconds.
+    pub release: f32,
+    /// Soften the peaks right below the ceiling, for a warmer sound when the limiter works hard.
+    pub soft_clip: bool,
+}
+
+impl Default for Limiter {
+    fn default() -> Limiter {
+        Limiter {
+            ceiling: -0.3,
+            lookahead: 0.005,
+            release: 0.1,
+            soft_clip: false,
+        }
+    }
+}
+
+/// How `pan` distributes a sound between the left and right channels.
+/// With both laws a centered sound plays at its original volume.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PanLaw {
+    /// Keeps the total power constant: a sound panned hard to one side
+    /// gets +3dB on that side.
+    ConstantPower,
+    /// Attenuates the opposite channel linearly, leaving the panned side untouched.
+    /// Sounds seem slightly louder in the center.
+    Linear,
 }
 
 impl Default for PlaySoundParams {
@@ -41,6 +138,15 @@ impl Default for PlaySoundParams {
         PlaySoundParams {
             looped: false,
             volume: 1.,
+            start_offset: 0.,
+            pitch: 1.,
+            pan: 0.,
+            pan_law: PanLaw::ConstantPower,
+            fade_in: 0.,
+            fade_in_curve: FadeCurve::Linear,
+            bus: Bus::MASTER,
+            sends: vec![],
+            emitter: None,
         }
     }
 }
@@ -0,0 +1,152 @@
+//! Look-ahead peak limiter for the master output.
+
+use crate::{Limiter, SAMPLE_RATE};
+
+use std::collections::VecDeque;
+
+/// `Limiter` settings turned into a running DSP state.
+///
+/// The signal is delayed by `lookahead` frames, while the gain is computed
+/// from the undelayed signal: the minimum required gain over the lookahead
+/// window, smoothed by a moving average of the same length. By the time a
+/// peak leaves the delay line, the gain has already ramped down to keep it
+/// below the ceiling, so there is no overshoot and no hard corners in the gain.
+#[derive(Debug)]
+pub(crate) struct MasterLimiter {
+    ceiling: f32,
+    soft_clip: bool,
+    lookahead: usize,
+    release_coef: f32,
+    /// Delayed stereo frames.
+    delay: VecDeque<[f32; 2]>,
+    /// Required gains over the lookahead window, as a monotonic queue:
+    /// (frame, gain) with gains increasing, so the front is the window minimum.
+    min_gains: VecDeque<(u64, f32)>,
+    /// Last `lookahead` window minimums, for the moving average.
+    average: VecDeque<f32>,
+    /// Sum of `average`, in f64 so that rounding errors of the running
+    /// additions and subtractions do not drift over hours of playback.
+    average_sum: f64,
+    gain: f32,
+    frame: u64,
+}
+
+impl MasterLimiter {
+    pub fn new(limiter: &Limiter) -> MasterLimiter {
+        let lookahead = ((limiter.lookahead.max(0.0) * SAMPLE_RATE as f32) as usize).max(1);
+        let release = limiter.release.max(0.001) * SAMPLE_RATE as f32;
+
+        let mut delay = VecDeque::with_capacity(lookahead + 1);
+        delay.extend(std::iter::repeat([0.0; 2]).take(lookahead));
+
+        let mut average = VecDeque::with_capacity(lookahead + 1);
+        average.extend(std::iter::repeat(1.0).take(lookahead));
+
+        MasterLimiter {
+            ceiling: db_to_gain(limiter.ceiling),
+            soft_clip: limiter.soft_clip,
+            lookahead,
+            release_coef: 1.0 - (-1.0 / release).exp(),
+            delay,
+            min_gains: VecDeque::with_capacity(lookahead + 2),
+            average,
+            average_sum: lookahead as f64,
+            gain: 1.0,
+            frame: 0,
+        }
+    }
+
+    /// Limit interleaved stereo `buffer` in place.
+    pub fn process(&mut self, buffer: &mut [f32]) {
+        for frame in buffer.chunks_exact_mut(2) {
+            let peak = frame[0].abs().max(frame[1].abs());
+            let required = if peak > self.ceiling {
+                self.ceiling / peak
+            } else {
+                1.0
+            };
+
+            // sliding window minimum over the last `lookahead + 1` frames
+            while matches!(self.min_gains.back(), Some(&(_, gain)) if gain >= required) {
+                self.min_gains.pop_back();
+            }
+            self.min_gains.push_back((self.frame, required));
+            while self.min_gains[0].0 + (self.lookahead as u64) < self.frame {
+                self.min_gains.pop_front();
+            }
+            let window_min = self.min_gains[0].1;
+
+            // moving average over the last `lookahead` minimums
+            self.average_sum += window_min as f64 - self.average.pop_front().unwrap() as f64;
+            self.average.push_back(window_min);
+            let target = ((self.average_sum / self.lookahead as f64) as f32).min(1.0);
+
+            // attack is already shaped by the moving average, release is exponential
+            self.gain = if target < self.gain {
+                target
+            } else {
+                self.gain + (target - self.gain) * self.release_coef
+            };
+
+            self.delay.push_back([frame[0], frame[1]]);
+            let [left, right] = self.delay.pop_front().unwrap();
+
+            frame[0] = left * self.gain;
+            frame[1] = right * self.gain;
+
+            if self.soft_clip {
+                frame[0] = soft_clip(frame[0], self.ceiling);
+                frame[1] = soft_clip(frame[1], self.ceiling);
+            }
+
+            self.frame += 1;
+        }
+    }
+}
+
+/// Leaves samples below 90% of the ceiling untouched and smoothly bends everything
+/// above it towards the ceiling, never reaching over it.
+fn soft_clip(sample: f32, ceiling: f32) -> f32 {
+    let knee = ceiling * 0.9;
+    let magnitude = sample.abs();
+
+    if magnitude <= knee {
+        return sample;
+    }
+
+    let range = ceiling - knee;
+    let clipped = knee + range * ((magnitude - knee) / range).tanh();
+
+    clipped.copysign(sample)
+}
+
+pub(crate) fn db_to_gain(db: f32) -> f32 {
+    10.0f32.powf(db / 20.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn moving_average_does_not_drift() {
+        let mut limiter = MasterLimiter::new(&Limiter::default());
+        let ceiling = limiter.ceiling;
+        let mut seed = 1u32;
+        let mut buffer = vec![0.0; 1024 * 2];
+
+        // ten minutes of loud noise, the gain changing on every frame
+        for _ in 0..SAMPLE_RATE as usize * 600 / 1024 {
+            for sample in &mut buffer {
+                seed = seed.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+                *sample = (seed >> 8) as f32 / (1 << 22) as f32 - 2.0;
+            }
+            limiter.process(&mut buffer);
+
+            assert!(buffer.iter().all(|sample| sample.abs() <= ceiling * 1.0001));
+        }
+
+        let exact: f64 = limiter.average.iter().map(|&gain| gain as f64).sum();
+        assert!((limiter.average_sum - exact).abs() < 1e-6);
+    }
+}
@@ -1,65 +1,429 @@
-use crate::{AudioContext, PlaySoundParams};
+use crate::{
+    ambisonic::{self, AmbisonicDecoder, AmbisonicFilters},
+    ducking::Ducker,
+    effects::{AudioEffect, EffectChain, EffectSlot, Reverb, ReverbSettings, Smoothed},
+    hrtf::{HrtfPanner, HrtfSet},
+    limiter::MasterLimiter,
+    ramp::{Ramp, DECLICK_FRAMES},
+    spatial::{self, Panning, PathFilter},
+    zones::ZonedReverb,
+    AmbisonicFormat, AudioContext, Ducking, Emitter, FadeCurve, Limiter, Listener, ListenerMix,
+    PanLaw, PlaySoundParams, ReverbZone, SAMPLE_RATE,
+};
 
-use std::cell::Cell;
+use std::any::{Any, TypeId};
+use std::cell::{Cell, RefCell};
 use std::collections::HashMap;
-use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicUsize, Ordering};
 use std::sync::mpsc;
+use std::sync::Arc;
+
+/// Data handed back by the audio thread, waiting for the control side to drop it.
+const GARBAGE_CAPACITY: usize = 64;
 
 enum AudioMessage {
-    AddSound(u32, Vec<f32>),
-    Play(u32, u32, bool, f32),
+    AddSound(u32, Vec<f32>, usize),
+    Play(u32, u32, PlaySoundParams, Arc<PlaybackState>),
+    Crossfade(u32, u32, u32, PlaySoundParams, Arc<PlaybackState>),
     Stop(u32),
+    StopWithFade(u32, usize, FadeCurve),
+    Seek(u32, usize),
+    Pause(u32),
+    PauseAll(u32),
+    Resume(u32),
+    ResumeAll(u32),
     StopAll(u32),
     SetVolume(u32, f32),
     SetVolumeAll(u32, f32),
+    Fade(u32, f32, usize, FadeCurve),
+    SetPitch(u32, f32),
+    SetPan(u32, f32),
+    SetEmitter(u32, Option<Emitter>),
+    SetEmitterPosition(u32, [f32; 3]),
+    SetEmitterVelocity(u32, [f32; 3]),
+    SetOcclusion(u32, f32),
+    SetObstruction(u32, f32),
+    SetListeners(Vec<Listener>, ListenerMix),
+    SetDoppler(f32, f32),
+    SetHrtf(Arc<HrtfSet>, AmbisonicFilters),
+    SetAmbisonicPanning(Panning),
     Delete(u32),
+    AddBus(u32, u32),
+    SetBusVolume(u32, f32),
+    SetBusMuted(u32, bool),
+    SetBusSolo(u32, bool),
+    SetBusDucking(u32, Option<Ducker>),
+    SetLimiter(Option<MasterLimiter>),
+    AddEffect(EffectTarget, u32, Box<dyn AudioEffect>),
+    RemoveEffect(u32),
+    SetEffectParameter(u32, u32, f32),
+    SetEffectData(u32, Box<dyn Any + Send>),
+    SetEffectMix(u32, f32),
+    SetEffectBypass(u32, bool),
+    ResetEffect(u32),
+    SetReverbZones(u32, ReverbSettings, Vec<ReverbZone>),
+}
+
+enum EffectTarget {
+    Bus(u32),
+    Playback(u32),
+}
+
+/// Whatever the audio thread stops using, handed back to be dropped on the control side:
+/// freeing memory on the audio thread may block it.
+enum Garbage {
+    EffectData(Box<dyn Any + Send>),
+    Effect(EffectSlot),
+    Playback(SoundState),
+    SoundData(Arc<[f32]>),
+    Limiter(MasterLimiter),
+    Hrtf(Arc<HrtfSet>, AmbisonicFilters),
+    Listeners(Vec<Listener>),
+    ReverbZones(Vec<ReverbZone>),
+}
+
+/// Playback state, published by the audio thread and read by `Playback`.
+#[derive(Debug)]
+struct PlaybackState {
+    playing: AtomicBool,
+    paused: AtomicBool,
+    frame: AtomicUsize,
+    /// Current speed, pitch times Doppler shift, as `f32` bits.
+    rate: AtomicU32,
+    frames: usize,
 }
 
 #[derive(Debug)]
 pub struct SoundState {
     sound_id: u32,
     play_id: u32,
-    sample: usize,
+    /// Read position, in frames. Fractional when pitch or doppler is not 1.0.
+    position: f64,
     data: Arc<[f32]>,
+    /// 2 for stereo, 4 for first order ambisonics.
+    channels: usize,
     looped: bool,
-    volume: f32,
+    volume: Ramp,
+    /// Fade out of `stop_with_fade` and crossfades. Apart from `volume`,
+    /// so that changing the volume meanwhile does not cut the fade short.
+    stop_gain: Ramp,
+    /// Remove the sound once `stop_gain` ramp is over.
+    stopping: bool,
+    pitch: f32,
+    /// Pitch multiplier from the emitter movement, updated every frame.
+    doppler: f32,
+    pan: f32,
+    pan_law: PanLaw,
+    paused: bool,
+    /// Declicks pausing and resuming, the sound stops being mixed once it reaches 0.0.
+    pause_gain: Ramp,
+    bus: u32,
+    emitter: Option<Emitter>,
+    /// Left and right gains from `emitter` at the end of the previous buffer,
+    /// ramped from towards the new ones over the next buffer.
+    spatial_gains: Option<[f32; 2]>,
+    /// Low-pass from the emitter cone and occlusion.
+    filter: PathFilter,
+    occlusion: Smoothed,
+    obstruction: Smoothed,
+    /// Low-pass of the direct path from obstruction.
+    obstruction_filter: PathFilter,
+    /// Binaural renderer, once the emitter used `Panning::Hrtf`.
+    hrtf: Option<HrtfPanner>,
+    /// Soundfield renderer of ambisonic sounds.
+    ambisonic: Option<AmbisonicDecoder>,
+    /// Aux send bus ids and levels.
+    sends: Vec<(u32, f32)>,
+    effects: EffectChain,
+    state: Arc<PlaybackState>,
 }
 
 impl SoundState {
-    fn get_samples(&mut self, n: usize) -> &[f32] {
-        let data = &self.data[self.sample..];
+    fn is_faded_out(&self) -> bool {
+        self.paused && self.pause_gain.is_finished()
+    }
+
+    /// Linearly interpolated frame at the current position, advancing position by `pitch` and `doppler`.
+    /// Stereo sounds only fill the first two channels. `None` once a non-looped sound is over.
+    fn next_frame(&mut self) -> Option<[f32; 4]> {
+        let channels = self.channels;
+        let frames = self.data.len() / channels;
+
+        if self.position >= frames as f64 {
+            if !self.looped || frames == 0 {
+                return None;
+            }
+            self.position %= frames as f64;
+        }
+
+        let ix = self.position as usize;
+        let next = if ix + 1 < frames {
+            ix + 1
+        } else if self.looped {
+            0
+        } else {
+            ix
+        };
+        let t = (self.position - ix as f64) as f32;
+
+        let mut frame = [0.0; 4];
+        for (channel, sample) in frame.iter_mut().take(channels).enumerate() {
+            *sample = self.data[ix * channels + channel] * (1.0 - t)
+                + self.data[next * channels + channel] * t;
+        }
+
+        self.position += (self.pitch * self.doppler) as f64;
+
+        Some(frame)
+    }
+
+    fn seek(&mut self, frame: usize) {
+        let frames = self.data.len() / self.channels;
+        let frame = if self.looped && frames != 0 {
+            frame % frames
+        } else {
+            frame.min(frames)
+        };
+
+        self.position = frame as f64;
+        // published here too, a playback seeked to its end or while paused is not mixed again
+        self.state.frame.store(frame, Ordering::Relaxed);
+    }
+
+    fn set_paused(&mut self, paused: bool) {
+        if paused != self.paused {
+            let gain = if paused { 0.0 } else { 1.0 };
+            self.pause_gain
+                .start(gain, DECLICK_FRAMES, FadeCurve::Linear);
+        }
 
-        self.sample += n;
+        self.paused = paused;
+        self.state.paused.store(paused, Ordering::Relaxed);
+    }
+}
+
+impl Drop for SoundState {
+    fn drop(&mut self) {
+        // whatever the reason sound was removed from the mixer - its playback is over
+        self.state.playing.store(false, Ordering::Relaxed);
+    }
+}
+
+/// Mixer side of a `Bus`.
+#[derive(Debug)]
+struct BusState {
+    parent: u32,
+    depth: usize,
+    volume: f32,
+    muted: bool,
+    solo: bool,
+    /// `volume`, or 0.0 while muted.
+    gain: Ramp,
+    /// 0.0 while some other bus is soloed. Applies to the bus's own playbacks only,
+    /// so a soloed bus is still heard through its parents.
+    solo_gain: Ramp,
+    /// Playbacks routed directly to this bus.
+    voices: Vec<f32>,
+    /// Sum of child bus outputs.
+    children: Vec<f32>,
+    effects: EffectChain,
+    ducker: Option<Ducker>,
+}
 
-        match data.get(..n) {
-            Some(data) => data,
-            None => data,
+impl BusState {
+    fn new(parent: u32, depth: usize) -> BusState {
+        BusState {
+            parent,
+            depth,
+            volume: 1.0,
+            muted: false,
+            solo: false,
+            gain: Ramp::new(1.0),
+            solo_gain: Ramp::new(1.0),
+            voices: vec![],
+            children: vec![],
+            effects: EffectChain::default(),
+            ducker: None,
         }
     }
 
-    fn rewind(&mut self) {
-        self.sample = 0;
+    fn update_gain(&mut self) {
+        let gain = if self.muted { 0.0 } else { self.volume };
+
+        self.gain.start(gain, DECLICK_FRAMES, FadeCurve::Linear);
     }
 }
 
 pub struct Mixer {
     rx: mpsc::Receiver<AudioMessage>,
-    sounds: HashMap<u32, Arc<[f32]>>,
+    garbage: mpsc::SyncSender<Garbage>,
+    /// Interleaved samples and their channel count.
+    sounds: HashMap<u32, (Arc<[f32]>, usize)>,
     mixer_state: Vec<SoundState>,
+    buses: HashMap<u32, BusState>,
+    /// Bus ids, children and ducking keys before the buses depending on them,
+    /// `Bus::MASTER` last.
+    bus_order: Vec<u32>,
+    limiter: Option<MasterLimiter>,
+    listeners: Vec<Listener>,
+    listener_mix: ListenerMix,
+    speed_of_sound: f32,
+    doppler_factor: f32,
+    hrtf: Arc<HrtfSet>,
+    ambisonic_filters: AmbisonicFilters,
+    ambisonic_panning: Panning,
+    reverb_zones: Vec<ZonedReverb>,
+    /// Single playback samples, before they go to the bus.
+    scratch: Vec<f32>,
+    /// Unfiltered copy of `scratch`, while a playback low-pass is crossfaded.
+    filter_dry: Vec<f32>,
 }
 
 pub struct MixerBuilder {
     rx: mpsc::Receiver<AudioMessage>,
+    garbage: mpsc::SyncSender<Garbage>,
+    hrtf: Arc<HrtfSet>,
+    ambisonic_filters: AmbisonicFilters,
 }
 
 pub struct MixerControl {
     tx: mpsc::Sender<AudioMessage>,
+    garbage: mpsc::Receiver<Garbage>,
     sound_id: Cell<u32>,
     play_id: Cell<u32>,
+    bus_id: Cell<u32>,
+    effect_id: Cell<u32>,
+    sound_frames: RefCell<HashMap<u32, usize>>,
+    bus_names: RefCell<Vec<(String, u32)>>,
+}
+
+/// A group of playbacks with its own volume, mute and solo.
+///
+/// Buses nest: playbacks are mixed into their bus, the bus output goes
+/// to its parent bus, and eventually everything ends up in `Bus::MASTER`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Bus {
+    pub(crate) id: u32,
+}
+
+impl Bus {
+    /// The bus directly connected to the output.
+    pub const MASTER: Bus = Bus { id: 0 };
+
+    pub fn new(ctx: &AudioContext, name: &str, parent: Bus) -> Bus {
+        ctx.mixer_ctrl.add_bus(name, parent)
+    }
+
+    /// Bus created with a given name, "Master" for `Bus::MASTER`.
+    pub fn find(ctx: &AudioContext, name: &str) -> Option<Bus> {
+        ctx.mixer_ctrl.find_bus(name)
+    }
+
+    pub fn set_volume(&self, ctx: &AudioContext, volume: f32) {
+        ctx.mixer_ctrl
+            .send(AudioMessage::SetBusVolume(self.id, volume));
+    }
+
+    pub fn set_muted(&self, ctx: &AudioContext, muted: bool) {
+        ctx.mixer_ctrl
+            .send(AudioMessage::SetBusMuted(self.id, muted));
+    }
+
+    /// While any bus is soloed, only playbacks in soloed buses and their child buses are heard.
+    pub fn set_solo(&self, ctx: &AudioContext, solo: bool) {
+        ctx.mixer_ctrl.send(AudioMessage::SetBusSolo(self.id, solo));
+    }
+
+    /// Automatically lower this bus volume while another bus is playing.
+    pub fn set_ducking(&self, ctx: &AudioContext, ducking: Option<Ducking>) {
+        ctx.mixer_ctrl.send(AudioMessage::SetBusDucking(
+            self.id,
+            ducking.as_ref().map(Ducker::new),
+        ));
+    }
+
+    /// Append an effect to the bus effect chain.
+    /// Effects process the sum of everything routed to the bus, before its volume.
+    pub fn add_effect(&self, ctx: &AudioContext, effect: impl AudioEffect + 'static) -> Effect {
+        ctx.mixer_ctrl
+            .add_effect(EffectTarget::Bus(self.id), effect)
+    }
+}
+
+/// An `AudioEffect` inserted into a bus or a playback.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Effect {
+    id: u32,
+    latency: usize,
+    /// Type of the `AudioEffect`, for the calls that only make sense with one effect.
+    type_id: TypeId,
+}
+
+impl Effect {
+    /// Delay the effect introduces, in seconds, as reported by `AudioEffect::latency`
+    /// when the effect was added.
+    pub fn latency_seconds(&self) -> f32 {
+        self.latency as f32 / SAMPLE_RATE as f32
+    }
+
+    pub fn set_parameter(&self, ctx: &AudioContext, index: u32, value: f32) {
+        ctx.mixer_ctrl
+            .send(AudioMessage::SetEffectParameter(self.id, index, value));
+    }
+
+    /// Send anything else the effect understands, for example a new `ImpulseResponse` to a `Convolver`.
+    pub fn set_data(&self, ctx: &AudioContext, data: impl Any + Send) {
+        ctx.mixer_ctrl
+            .send(AudioMessage::SetEffectData(self.id, Box::new(data)));
+    }
+
+    /// Wet/dry balance, from 0.0 (only the unprocessed signal) to 1.0 (only the effect output).
+    pub fn set_mix(&self, ctx: &AudioContext, mix: f32) {
+        ctx.mixer_ctrl
+            .send(AudioMessage::SetEffectMix(self.id, mix));
+    }
+
+    /// Bypassed effects pass the signal through untouched and are not processed at all.
+    pub fn set_bypass(&self, ctx: &AudioContext, bypass: bool) {
+        ctx.mixer_ctrl
+            .send(AudioMessage::SetEffectBypass(self.id, bypass));
+    }
+
+    pub fn reset(&self, ctx: &AudioContext) {
+        ctx.mixer_ctrl.send(AudioMessage::ResetEffect(self.id));
+    }
+
+    pub fn remove(self, ctx: &AudioContext) {
+        ctx.mixer_ctrl.send(AudioMessage::RemoveEffect(self.id));
+    }
+
+    /// Drive the parameters of this effect, which must be a `Reverb`, from the listener position:
+    /// `outside` of all the zones, crossfading into each zone settings when entering it.
+    /// With several listeners, their settings are averaged.
+    ///
+    /// Later zones take precedence where zones overlap, so list nested zones
+    /// after the ones containing them. An empty `zones` stops driving the effect.
+    ///
+    /// Panics if the effect is not a `Reverb`.
+    pub fn set_reverb_zones(
+        &self,
+        ctx: &AudioContext,
+        outside: ReverbSettings,
+        zones: Vec<ReverbZone>,
+    ) {
+        assert!(
+            self.type_id == TypeId::of::<Reverb>(),
+            "reverb zones can only drive a Reverb effect"
+        );
+
+        ctx.mixer_ctrl
+            .send(AudioMessage::SetReverbZones(self.id, outside, zones));
+    }
 }
 
 pub struct Playback {
     play_id: u32,
+    state: Arc<PlaybackState>,
 }
 
 impl Playback {
@@ -67,20 +431,154 @@ impl Playback {
         ctx.mixer_ctrl.send(AudioMessage::Stop(self.play_id));
     }
 
+    /// Fade out over `duration` seconds, then stop.
+    pub fn stop_with_fade(self, ctx: &AudioContext, duration: f32, curve: FadeCurve) {
+        ctx.mixer_ctrl.send(AudioMessage::StopWithFade(
+            self.play_id,
+            seconds_to_frames(duration),
+            curve,
+        ));
+    }
+
     pub fn set_volume(&self, ctx: &AudioContext, volume: f32) {
         ctx.mixer_ctrl
             .send(AudioMessage::SetVolume(self.play_id, volume));
     }
+
+    /// Ramp volume from its current value to `volume` over `duration` seconds.
+    pub fn fade_to(&self, ctx: &AudioContext, volume: f32, duration: f32, curve: FadeCurve) {
+        ctx.mixer_ctrl.send(AudioMessage::Fade(
+            self.play_id,
+            volume,
+            seconds_to_frames(duration),
+            curve,
+        ));
+    }
+
+    /// Playback speed. 1.0 is the original pitch, 2.0 is twice as fast and an octave higher.
+    pub fn set_pitch(&self, ctx: &AudioContext, pitch: f32) {
+        ctx.mixer_ctrl
+            .send(AudioMessage::SetPitch(self.play_id, pitch));
+    }
+
+    /// Stereo position, from -1.0 (left) through 0.0 (center) to 1.0 (right).
+    pub fn set_pan(&self, ctx: &AudioContext, pan: f32) {
+        ctx.mixer_ctrl.send(AudioMessage::SetPan(self.play_id, pan));
+    }
+
+    /// Make the playback positional, or plain stereo again with `None`.
+    pub fn set_emitter(&self, ctx: &AudioContext, emitter: Option<Emitter>) {
+        ctx.mixer_ctrl
+            .send(AudioMessage::SetEmitter(self.play_id, emitter));
+    }
+
+    /// Change the velocity of a positional playback emitter, for the Doppler effect.
+    pub fn set_velocity(&self, ctx: &AudioContext, velocity: [f32; 3]) {
+        ctx.mixer_ctrl
+            .send(AudioMessage::SetEmitterVelocity(self.play_id, velocity));
+    }
+
+    /// Move the emitter of a positional playback, keeping its other settings.
+    pub fn set_position(&self, ctx: &AudioContext, position: [f32; 3]) {
+        ctx.mixer_ctrl
+            .send(AudioMessage::SetEmitterPosition(self.play_id, position));
+    }
+
+    /// Muffle the playback as if heard through a wall, from 0.0 (clear) to 1.0 (fully occluded).
+    /// Lowers the volume and the low-pass cutoff of everything, aux sends included.
+    /// Changes are smoothed, so this can be fed raw raycast results every game frame.
+    pub fn set_occlusion(&self, ctx: &AudioContext, occlusion: f32) {
+        ctx.mixer_ctrl
+            .send(AudioMessage::SetOcclusion(self.play_id, occlusion));
+    }
+
+    /// Like `set_occlusion`, for obstacles in the same room as the listener:
+    /// only the direct sound is muffled, aux sends like reverb stay as they are.
+    pub fn set_obstruction(&self, ctx: &AudioContext, obstruction: f32) {
+        ctx.mixer_ctrl
+            .send(AudioMessage::SetObstruction(self.play_id, obstruction));
+    }
+
+    /// Append an effect to the playback effect chain.
+    /// Effects are removed together with the playback, cutting off their tails.
+    pub fn add_effect(&self, ctx: &AudioContext, effect: impl AudioEffect + 'static) -> Effect {
+        ctx.mixer_ctrl
+            .add_effect(EffectTarget::Playback(self.play_id), effect)
+    }
+
+    /// Move playback to a given position in the sound, in seconds.
+    pub fn seek(&self, ctx: &AudioContext, position: f32) {
+        ctx.mixer_ctrl.send(AudioMessage::Seek(
+            self.play_id,
+            seconds_to_frames(position),
+        ));
+    }
+
+    /// Stop mixing the playback, keeping its position, until `resume` is called.
+    pub fn pause(&self, ctx: &AudioContext) {
+        ctx.mixer_ctrl.send(AudioMessage::Pause(self.play_id));
+    }
+
+    pub fn resume(&self, ctx: &AudioContext) {
+        ctx.mixer_ctrl.send(AudioMessage::Resume(self.play_id));
+    }
+
+    /// `false` once the playback reached the end of a non-looped sound or was stopped.
+    /// Paused playback is still playing.
+    pub fn is_playing(&self) -> bool {
+        self.state.playing.load(Ordering::Relaxed)
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.state.paused.load(Ordering::Relaxed)
+    }
+
+    /// Current position in the sound, in seconds.
+    /// Wraps back to 0 on each iteration of a looped playback.
+    pub fn position(&self) -> f32 {
+        self.state.frame.load(Ordering::Relaxed) as f32 / SAMPLE_RATE as f32
+    }
+
+    /// Time left until the end of the sound, in seconds, at the current pitch and Doppler shift.
+    /// For looped playbacks - until the end of the current iteration.
+    pub fn remaining(&self) -> f32 {
+        let frame = self.state.frame.load(Ordering::Relaxed);
+        let frames = self.state.frames.saturating_sub(frame);
+        let rate = f32::from_bits(self.state.rate.load(Ordering::Relaxed));
+
+        if frames == 0 {
+            return 0.0;
+        }
+
+        // infinite at pitch 0.0
+        frames as f32 / SAMPLE_RATE as f32 / rate
+    }
 }
 
 impl MixerControl {
     pub fn load(&self, data: &[u8]) -> u32 {
+        let samples = load_samples_from_file(data).unwrap();
+
+        self.add_sound(samples, 2)
+    }
+
+    pub fn load_ambisonic(&self, data: &[u8], format: AmbisonicFormat) -> u32 {
+        let samples = load_ambisonic_samples_from_file(data, format).unwrap();
+
+        self.add_sound(samples, 4)
+    }
+
+    fn add_sound(&self, samples: Vec<f32>, channels: usize) -> u32 {
         let sound_id = self.sound_id.get();
 
-        let samples = load_samples_from_file(data).unwrap();
+        self.sound_frames
+            .borrow_mut()
+            .insert(sound_id, samples.len() / channels);
 
         self.tx
-            .send(crate::mixer::AudioMessage::AddSound(sound_id, samples))
+            .send(crate::mixer::AudioMessage::AddSound(
+                sound_id, samples, channels,
+            ))
             .unwrap_or_else(|_| println!("Audio thread died"));
         self.sound_id.set(sound_id + 1);
 
@@ -88,18 +586,116 @@ impl MixerControl {
     }
 
     pub fn play(&self, sound_id: u32, params: PlaySoundParams) -> Playback {
-        let play_id = self.play_id.get();
+        let playback = self.new_playback(sound_id, params.pitch);
 
         self.send(AudioMessage::Play(
             sound_id,
-            play_id,
-            params.looped,
-            params.volume,
+            playback.play_id,
+            params,
+            playback.state.clone(),
         ));
 
+        playback
+    }
+
+    /// Start playing `sound_id` while fading `from` out, both with equal power curves.
+    /// Both ramps start at exactly the same sample.
+    pub fn crossfade(
+        &self,
+        from: Playback,
+        sound_id: u32,
+        params: PlaySoundParams,
+        duration: f32,
+    ) -> Playback {
+        let playback = self.new_playback(sound_id, params.pitch);
+
+        self.send(AudioMessage::Crossfade(
+            from.play_id,
+            sound_id,
+            playback.play_id,
+            PlaySoundParams {
+                fade_in: duration,
+                fade_in_curve: FadeCurve::EqualPower,
+                ..params
+            },
+            playback.state.clone(),
+        ));
+
+        playback
+    }
+
+    fn new_playback(&self, sound_id: u32, pitch: f32) -> Playback {
+        let play_id = self.play_id.get();
+
+        let state = Arc::new(PlaybackState {
+            playing: AtomicBool::new(true),
+            paused: AtomicBool::new(false),
+            frame: AtomicUsize::new(0),
+            rate: AtomicU32::new(pitch.max(0.0).to_bits()),
+            frames: self.sound_frames(sound_id),
+        });
+
         self.play_id.set(play_id + 1);
 
-        Playback { play_id }
+        Playback { play_id, state }
+    }
+
+    pub fn add_bus(&self, name: &str, parent: Bus) -> Bus {
+        let bus_id = self.bus_id.get();
+
+        self.bus_names.borrow_mut().push((name.to_string(), bus_id));
+        self.send(AudioMessage::AddBus(bus_id, parent.id));
+
+        self.bus_id.set(bus_id + 1);
+
+        Bus { id: bus_id }
+    }
+
+    pub fn find_bus(&self, name: &str) -> Option<Bus> {
+        self.bus_names
+            .borrow()
+            .iter()
+            .find(|(bus_name, _)| bus_name == name)
+            .map(|&(_, id)| Bus { id })
+    }
+
+    fn add_effect<E: AudioEffect + 'static>(&self, target: EffectTarget, effect: E) -> Effect {
+        let effect_id = self.effect_id.get();
+        let latency = effect.latency();
+
+        self.send(AudioMessage::AddEffect(target, effect_id, Box::new(effect)));
+
+        self.effect_id.set(effect_id + 1);
+
+        Effect {
+            id: effect_id,
+            latency,
+            type_id: TypeId::of::<E>(),
+        }
+    }
+
+    pub fn set_limiter(&self, limiter: Option<Limiter>) {
+        self.send(AudioMessage::SetLimiter(
+            limiter.as_ref().map(MasterLimiter::new),
+        ));
+    }
+
+    pub fn set_listeners(&self, listeners: &[Listener], mix: ListenerMix) {
+        self.send(AudioMessage::SetListeners(listeners.to_vec(), mix));
+    }
+
+    pub fn set_doppler(&self, speed_of_sound: f32, doppler_factor: f32) {
+        self.send(AudioMessage::SetDoppler(speed_of_sound, doppler_factor));
+    }
+
+    pub fn set_hrtf(&self, hrtf: HrtfSet) {
+        let filters = AmbisonicFilters::new(&hrtf);
+
+        self.send(AudioMessage::SetHrtf(Arc::new(hrtf), filters));
+    }
+
+    pub fn set_ambisonic_panning(&self, panning: Panning) {
+        self.send(AudioMessage::SetAmbisonicPanning(panning));
     }
 
     pub fn stop(&self, play_id: u32) {
@@ -110,15 +706,40 @@ impl MixerControl {
         self.send(AudioMessage::StopAll(sound_id));
     }
 
+    pub fn pause_all(&self, sound_id: u32) {
+        self.send(AudioMessage::PauseAll(sound_id));
+    }
+
+    pub fn resume_all(&self, sound_id: u32) {
+        self.send(AudioMessage::ResumeAll(sound_id));
+    }
+
     pub fn set_volume_all(&self, sound_id: u32, volume: f32) {
         self.send(AudioMessage::SetVolumeAll(sound_id, volume));
     }
 
     pub fn delete(&self, sound_id: u32) {
+        self.sound_frames.borrow_mut().remove(&sound_id);
         self.send(AudioMessage::Delete(sound_id));
     }
 
+    /// Length of the sound, in seconds.
+    pub fn duration(&self, sound_id: u32) -> f32 {
+        self.sound_frames(sound_id) as f32 / SAMPLE_RATE as f32
+    }
+
+    fn sound_frames(&self, sound_id: u32) -> usize {
+        self.sound_frames
+            .borrow()
+            .get(&sound_id)
+            .copied()
+            .unwrap_or(0)
+    }
+
     fn send(&self, message: AudioMessage) {
+        // whatever the audio thread handed back is freed here
+        for _ in self.garbage.try_iter() {}
+
         self.tx
             .send(message)
             .unwrap_or_else(|_| println!("Audio thread died"))
@@ -127,10 +748,27 @@ impl MixerControl {
 
 impl MixerBuilder {
     pub fn build(self) -> Mixer {
+        let mut buses = HashMap::new();
+        buses.insert(Bus::MASTER.id, BusState::new(Bus::MASTER.id, 0));
+
         Mixer {
             rx: self.rx,
+            garbage: self.garbage,
             sounds: HashMap::new(),
             mixer_state: vec![],
+            buses,
+            bus_order: vec![Bus::MASTER.id],
+            limiter: None,
+            listeners: vec![Listener::default()],
+            listener_mix: ListenerMix::Nearest,
+            speed_of_sound: 343.3,
+            doppler_factor: 1.0,
+            hrtf: self.hrtf,
+            ambisonic_filters: self.ambisonic_filters,
+            ambisonic_panning: Panning::Stereo,
+            reverb_zones: vec![],
+            scratch: vec![],
+            filter_dry: vec![],
         }
     }
 }
@@ -138,51 +776,281 @@ impl MixerBuilder {
 impl Mixer {
     pub fn new() -> (MixerBuilder, MixerControl) {
         let (tx, rx) = mpsc::channel();
+        let (garbage_tx, garbage) = mpsc::sync_channel(GARBAGE_CAPACITY);
+        let hrtf = HrtfSet::spherical_head();
 
         (
-            MixerBuilder { rx },
+            MixerBuilder {
+                rx,
+                garbage: garbage_tx,
+                ambisonic_filters: AmbisonicFilters::new(&hrtf),
+                hrtf: Arc::new(hrtf),
+            },
             MixerControl {
                 tx,
+                garbage,
                 sound_id: Cell::new(0),
                 play_id: Cell::new(0),
+                bus_id: Cell::new(Bus::MASTER.id + 1),
+                effect_id: Cell::new(0),
+                sound_frames: RefCell::new(HashMap::new()),
+                bus_names: RefCell::new(vec![("Master".to_string(), Bus::MASTER.id)]),
             },
         )
     }
 
+    fn play(
+        &mut self,
+        sound_id: u32,
+        play_id: u32,
+        params: PlaySoundParams,
+        state: Arc<PlaybackState>,
+    ) {
+        let (data, channels) = match self.sounds.get(&sound_id) {
+            Some(&(ref data, channels)) => (data, channels),
+            None => {
+                state.playing.store(false, Ordering::Relaxed);
+                return;
+            }
+        };
+
+        let mut volume = Ramp::new(0.0);
+        volume.start(
+            params.volume,
+            seconds_to_frames(params.fade_in),
+            params.fade_in_curve,
+        );
+
+        let mut sound = SoundState {
+            sound_id,
+            play_id,
+            position: 0.0,
+            data: data.clone(),
+            channels,
+            looped: params.looped,
+            volume,
+            stop_gain: Ramp::new(1.0),
+            stopping: false,
+            pitch: params.pitch.max(0.0),
+            doppler: 1.0,
+            pan: params.pan,
+            pan_law: params.pan_law,
+            paused: false,
+            pause_gain: Ramp::new(1.0),
+            bus: if self.buses.contains_key(&params.bus.id) {
+                params.bus.id
+            } else {
+                Bus::MASTER.id
+            },
+            emitter: params.emitter,
+            spatial_gains: None,
+            filter: PathFilter::default(),
+            occlusion: Smoothed::new(0.0),
+            obstruction: Smoothed::new(0.0),
+            obstruction_filter: PathFilter::default(),
+            hrtf: None,
+            ambisonic: if channels == 4 {
+                Some(AmbisonicDecoder::new())
+            } else {
+                None
+            },
+            sends: params
+                .sends
+                .iter()
+                .filter(|(bus, _)| self.buses.contains_key(&bus.id))
+                .map(|&(bus, level)| (bus.id, level))
+                .collect(),
+            effects: EffectChain::default(),
+            state,
+        };
+        sound.seek(seconds_to_frames(params.start_offset));
+
+        self.mixer_state.push(sound);
+    }
+
+    fn add_bus(&mut self, id: u32, parent: u32) {
+        let parent = if self.buses.contains_key(&parent) {
+            parent
+        } else {
+            Bus::MASTER.id
+        };
+        let depth = self.buses[&parent].depth + 1;
+
+        self.buses.insert(id, BusState::new(parent, depth));
+
+        self.update_bus_order();
+        self.update_solo();
+    }
+
+    /// Recalculate `bus_order`, after the bus tree or ducking keys changed.
+    fn update_bus_order(&mut self) {
+        fn visit(
+            buses: &HashMap<u32, BusState>,
+            id: u32,
+            visiting: &mut Vec<u32>,
+            order: &mut Vec<u32>,
+        ) {
+            if order.contains(&id) || visiting.contains(&id) {
+                return;
+            }
+            visiting.push(id);
+
+            let mut children: Vec<u32> = buses
+                .iter()
+                .filter(|&(&child, bus)| bus.parent == id && child != id)
+                .map(|(&child, _)| child)
+                .collect();
+            children.sort_unstable();
+            for child in children {
+                visit(buses, child, visiting, order);
+            }
+
+            // a key that is also a parent would be a cycle, it is processed after the
+            // ducked bus and only its own playbacks, before effects and volume, are the key
+            if let Some(ducker) = &buses[&id].ducker {
+                if buses.contains_key(&ducker.key) {
+                    visit(buses, ducker.key, visiting, order);
+                }
+            }
+
+            visiting.pop();
+            order.push(id);
+        }
+
+        let mut order = Vec::with_capacity(self.buses.len());
+        visit(&self.buses, Bus::MASTER.id, &mut vec![], &mut order);
+
+        self.bus_order = order;
+    }
+
+    /// Recalculate `solo_gain` of all the buses, after some bus solo changed.
+    fn update_solo(&mut self) {
+        let any_solo = self.buses.values().any(|bus| bus.solo);
+        let audible: Vec<(u32, bool)> = self
+            .buses
+            .keys()
+            .map(|&id| (id, !any_solo || self.is_soloed(id)))
+            .collect();
+
+        for (id, audible) in audible {
+            let gain = if audible { 1.0 } else { 0.0 };
+
+            self.buses.get_mut(&id).unwrap().solo_gain.start(
+                gain,
+                DECLICK_FRAMES,
+                FadeCurve::Linear,
+            );
+        }
+    }
+
+    /// Is the bus itself or any of its parents soloed.
+    fn is_soloed(&self, mut id: u32) -> bool {
+        loop {
+            let bus = &self.buses[&id];
+
+            if bus.solo {
+                return true;
+            }
+            if id == Bus::MASTER.id {
+                return false;
+            }
+            id = bus.parent;
+        }
+    }
+
+    /// Hand `garbage` to the control side to be dropped there.
+    fn discard(&self, garbage: Garbage) {
+        // the channel is only full if nothing was sent for a long time,
+        // the garbage is dropped here then
+        let _ = self.garbage.try_send(garbage);
+    }
+
+    fn remove_playback(&mut self, index: usize) {
+        let sound = self.mixer_state.swap_remove(index);
+
+        // over now, not once the control side drops it
+        sound.state.playing.store(false, Ordering::Relaxed);
+        self.discard(Garbage::Playback(sound));
+    }
+
+    fn effect_mut(&mut self, id: u32) -> Option<&mut EffectSlot> {
+        let buses = self.buses.values_mut().map(|bus| &mut bus.effects);
+        let sounds = self.mixer_state.iter_mut().map(|sound| &mut sound.effects);
+
+        buses.chain(sounds).find_map(|effects| effects.get_mut(id))
+    }
+
     pub fn fill_audio_buffer(&mut self, buffer: &mut [f32], frames: usize) {
         while let Ok(message) = self.rx.try_recv() {
             match message {
-                AudioMessage::AddSound(id, data) => {
-                    self.sounds.insert(id, data.into());
-                }
-                AudioMessage::Play(sound_id, play_id, looped, volume) => {
-                    if let Some(data) = self.sounds.get(&sound_id) {
-                        self.mixer_state.push(SoundState {
-                            sound_id,
-                            play_id,
-                            sample: 0,
-                            data: data.clone(),
-                            looped,
-                            volume,
-                        });
+                AudioMessage::AddSound(id, data, channels) => {
+                    self.sounds.insert(id, (data.into(), channels));
+                }
+                AudioMessage::Play(sound_id, play_id, params, state) => {
+                    self.play(sound_id, play_id, params, state);
+                }
+                AudioMessage::Crossfade(from, sound_id, play_id, params, state) => {
+                    if let Some(sound) = self.mixer_state.iter_mut().find(|s| s.play_id == from) {
+                        sound.stop_gain.start(
+                            0.0,
+                            seconds_to_frames(params.fade_in),
+                            params.fade_in_curve,
+                        );
+                        sound.stopping = true;
                     }
+
+                    self.play(sound_id, play_id, params, state);
                 }
                 AudioMessage::Stop(play_id) => {
                     if let Some(i) = self.mixer_state.iter().position(|s| s.play_id == play_id) {
-                        self.mixer_state.swap_remove(i);
+                        self.remove_playback(i);
+                    }
+                }
+                AudioMessage::StopWithFade(play_id, frames, curve) => {
+                    if let Some(sound) = self.mixer_state.iter_mut().find(|s| s.play_id == play_id)
+                    {
+                        sound.stop_gain.start(0.0, frames, curve);
+                        sound.stopping = true;
+                    }
+                }
+                AudioMessage::Seek(play_id, frame) => {
+                    if let Some(sound) = self.mixer_state.iter_mut().find(|s| s.play_id == play_id)
+                    {
+                        sound.seek(frame);
+                    }
+                }
+                AudioMessage::Pause(play_id) | AudioMessage::Resume(play_id) => {
+                    let paused = matches!(message, AudioMessage::Pause(_));
+
+                    if let Some(sound) = self.mixer_state.iter_mut().find(|s| s.play_id == play_id)
+                    {
+                        sound.set_paused(paused);
+                    }
+                }
+                AudioMessage::PauseAll(sound_id) | AudioMessage::ResumeAll(sound_id) => {
+                    let paused = matches!(message, AudioMessage::PauseAll(_));
+
+                    for sound in self
+                        .mixer_state
+                        .iter_mut()
+                        .filter(|s| s.sound_id == sound_id)
+                    {
+                        sound.set_paused(paused);
                     }
                 }
                 AudioMessage::StopAll(sound_id) => {
                     for i in (0..self.mixer_state.len()).rev() {
                         if self.mixer_state[i].sound_id == sound_id {
-                            self.mixer_state.swap_remove(i);
+                            self.remove_playback(i);
                         }
                     }
                 }
                 AudioMessage::SetVolume(play_id, volume) => {
                     if let Some(sound) = self.mixer_state.iter_mut().find(|s| s.play_id == play_id)
                     {
-                        sound.volume = volume;
+                        sound
+                            .volume
+                            .start(volume, DECLICK_FRAMES, FadeCurve::Linear);
                     }
                 }
                 AudioMessage::SetVolumeAll(sound_id, volume) => {
@@ -191,98 +1059,562 @@ impl Mixer {
                         .iter_mut()
                         .filter(|s| s.sound_id == sound_id)
                     {
-                        sound.volume = volume;
+                        sound
+                            .volume
+                            .start(volume, DECLICK_FRAMES, FadeCurve::Linear);
+                    }
+                }
+                AudioMessage::Fade(play_id, volume, frames, curve) => {
+                    if let Some(sound) = self.mixer_state.iter_mut().find(|s| s.play_id == play_id)
+                    {
+                        sound.volume.start(volume, frames, curve);
+                    }
+                }
+                AudioMessage::SetPitch(play_id, pitch) => {
+                    if let Some(sound) = self.mixer_state.iter_mut().find(|s| s.play_id == play_id)
+                    {
+                        sound.pitch = pitch.max(0.0);
+                    }
+                }
+                AudioMessage::SetPan(play_id, pan) => {
+                    if let Some(sound) = self.mixer_state.iter_mut().find(|s| s.play_id == play_id)
+                    {
+                        sound.pan = pan;
+                    }
+                }
+                AudioMessage::SetEmitter(play_id, emitter) => {
+                    if let Some(sound) = self.mixer_state.iter_mut().find(|s| s.play_id == play_id)
+                    {
+                        sound.emitter = emitter;
+                        sound.spatial_gains = None;
+                    }
+                }
+                AudioMessage::SetEmitterPosition(play_id, position) => {
+                    if let Some(emitter) = self
+                        .mixer_state
+                        .iter_mut()
+                        .find(|s| s.play_id == play_id)
+                        .and_then(|sound| sound.emitter.as_mut())
+                    {
+                        emitter.position = position;
+                    }
+                }
+                AudioMessage::SetEmitterVelocity(play_id, velocity) => {
+                    if let Some(emitter) = self
+                        .mixer_state
+                        .iter_mut()
+                        .find(|s| s.play_id == play_id)
+                        .and_then(|sound| sound.emitter.as_mut())
+                    {
+                        emitter.velocity = velocity;
+                    }
+                }
+                AudioMessage::SetOcclusion(play_id, occlusion) => {
+                    if let Some(sound) = self.mixer_state.iter_mut().find(|s| s.play_id == play_id)
+                    {
+                        sound.occlusion.target = occlusion.clamp(0.0, 1.0);
+                    }
+                }
+                AudioMessage::SetObstruction(play_id, obstruction) => {
+                    if let Some(sound) = self.mixer_state.iter_mut().find(|s| s.play_id == play_id)
+                    {
+                        sound.obstruction.target = obstruction.clamp(0.0, 1.0);
                     }
                 }
+                AudioMessage::SetListeners(listeners, mix) => {
+                    let listeners = std::mem::replace(&mut self.listeners, listeners);
+                    self.listener_mix = mix;
+                    self.discard(Garbage::Listeners(listeners));
+                }
+                AudioMessage::SetHrtf(hrtf, filters) => {
+                    let hrtf = std::mem::replace(&mut self.hrtf, hrtf);
+                    let filters = std::mem::replace(&mut self.ambisonic_filters, filters);
+                    self.discard(Garbage::Hrtf(hrtf, filters));
+                }
+                AudioMessage::SetAmbisonicPanning(panning) => {
+                    self.ambisonic_panning = panning;
+                }
+                AudioMessage::SetDoppler(speed_of_sound, doppler_factor) => {
+                    self.speed_of_sound = speed_of_sound;
+                    self.doppler_factor = doppler_factor.max(0.0);
+                }
                 AudioMessage::Delete(sound_id) => {
                     for i in (0..self.mixer_state.len()).rev() {
                         if self.mixer_state[i].sound_id == sound_id {
-                            self.mixer_state.swap_remove(i);
+                            self.remove_playback(i);
                         }
                     }
-                    self.sounds.remove(&sound_id);
+                    if let Some((data, _)) = self.sounds.remove(&sound_id) {
+                        self.discard(Garbage::SoundData(data));
+                    }
+                }
+                AudioMessage::AddBus(id, parent) => {
+                    self.add_bus(id, parent);
+                }
+                AudioMessage::SetBusVolume(id, volume) => {
+                    if let Some(bus) = self.buses.get_mut(&id) {
+                        bus.volume = volume;
+                        bus.update_gain();
+                    }
+                }
+                AudioMessage::SetBusMuted(id, muted) => {
+                    if let Some(bus) = self.buses.get_mut(&id) {
+                        bus.muted = muted;
+                        bus.update_gain();
+                    }
+                }
+                AudioMessage::SetBusSolo(id, solo) => {
+                    if let Some(bus) = self.buses.get_mut(&id) {
+                        bus.solo = solo;
+                        self.update_solo();
+                    }
+                }
+                AudioMessage::SetBusDucking(id, ducker) => {
+                    if let Some(bus) = self.buses.get_mut(&id) {
+                        bus.ducker = ducker;
+                        self.update_bus_order();
+                    }
+                }
+                AudioMessage::SetLimiter(limiter) => {
+                    if let Some(limiter) = std::mem::replace(&mut self.limiter, limiter) {
+                        self.discard(Garbage::Limiter(limiter));
+                    }
+                }
+                AudioMessage::AddEffect(target, id, effect) => {
+                    let effects = match target {
+                        EffectTarget::Bus(bus_id) => {
+                            self.buses.get_mut(&bus_id).map(|bus| &mut bus.effects)
+                        }
+                        EffectTarget::Playback(play_id) => self
+                            .mixer_state
+                            .iter_mut()
+                            .find(|s| s.play_id == play_id)
+                            .map(|sound| &mut sound.effects),
+                    };
+
+                    if let Some(effects) = effects {
+                        effects.push(id, effect);
+                    }
+                }
+                AudioMessage::RemoveEffect(id) => {
+                    let buses = self.buses.values_mut().map(|bus| &mut bus.effects);
+                    let sounds = self.mixer_state.iter_mut().map(|sound| &mut sound.effects);
+
+                    let removed = buses.chain(sounds).find_map(|effects| effects.remove(id));
+
+                    if let Some(slot) = removed {
+                        self.discard(Garbage::Effect(slot));
+                    }
+                }
+                AudioMessage::SetEffectParameter(id, index, value) => {
+                    if let Some(effect) = self.effect_mut(id) {
+                        effect.set_parameter(index, value);
+                    }
+                }
+                AudioMessage::SetEffectData(id, data) => {
+                    let garbage = match self.effect_mut(id) {
+                        Some(effect) => effect.set_data(data),
+                        None => Some(data),
+                    };
+
+                    if let Some(garbage) = garbage {
+                        self.discard(Garbage::EffectData(garbage));
+                    }
+                }
+                AudioMessage::SetEffectMix(id, mix) => {
+                    if let Some(effect) = self.effect_mut(id) {
+                        effect.set_mix(mix);
+                    }
+                }
+                AudioMessage::SetEffectBypass(id, bypass) => {
+                    if let Some(effect) = self.effect_mut(id) {
+                        effect.set_bypass(bypass);
+                    }
+                }
+                AudioMessage::ResetEffect(id) => {
+                    if let Some(effect) = self.effect_mut(id) {
+                        effect.reset();
+                    }
+                }
+                AudioMessage::SetReverbZones(effect, outside, zones) => {
+                    if let Some(i) = self.reverb_zones.iter().position(|z| z.effect == effect) {
+                        let zoned = self.reverb_zones.swap_remove(i);
+                        self.discard(Garbage::ReverbZones(zoned.zones));
+                    }
+
+                    if !zones.is_empty() {
+                        self.reverb_zones.push(ZonedReverb {
+                            effect,
+                            outside,
+                            zones,
+                            current: None,
+                        });
+                    }
                 }
             }
         }
 
-        // zeroize the buffer
-        buffer.fill(0.0);
+        // reverb zones follow the listeners once per buffer, the effect smooths the rest
+        let mut reverb_zones = std::mem::take(&mut self.reverb_zones);
+        let mut i = 0;
+        while let Some(zoned) = reverb_zones.get_mut(i) {
+            let settings = zoned.settings(&self.listeners);
+            let effect = match self.effect_mut(zoned.effect) {
+                Some(effect) => effect,
+                // the effect was removed
+                None => {
+                    let zoned = reverb_zones.swap_remove(i);
+                    self.discard(Garbage::ReverbZones(zoned.zones));
+                    continue;
+                }
+            };
+
+            if zoned.current != Some(settings) {
+                zoned.current = Some(settings);
+                for (index, value) in settings.parameters() {
+                    effect.set_parameter(index, value);
+                }
+            }
+            i += 1;
+        }
+        self.reverb_zones = reverb_zones;
+
+        for bus in self.buses.values_mut() {
+            bus.voices.clear();
+            bus.voices.resize(buffer.len(), 0.0);
+            bus.children.clear();
+            bus.children.resize(buffer.len(), 0.0);
+        }
 
         // Note: Doing manual iteration so we can remove sounds that finished playing
         let mut i = 0;
 
         while let Some(sound) = self.mixer_state.get_mut(i) {
-            let volume = sound.volume;
-            let mut remainder = buffer.len();
+            if sound.is_faded_out() {
+                i += 1;
+                continue;
+            }
+
+            // spatial gains and doppler are evaluated once per buffer and interpolated in between
+            let (listeners, mix) = (&self.listeners, self.listener_mix);
+            let (speed_of_sound, doppler_factor) = (self.speed_of_sound, self.doppler_factor);
+            // soundfields have no position, they only turn with the listener
+            let spatialized = sound
+                .emitter
+                .filter(|_| sound.ambisonic.is_none())
+                .map(|emitter| {
+                    spatial::spatialize(listeners, mix, &emitter, speed_of_sound, doppler_factor)
+                });
+            let from_doppler = sound.doppler;
+            let to_doppler = spatialized.map_or(1.0, |s| s.doppler);
+            let (from_gains, to_gains) = match (&sound.emitter, spatialized) {
+                (Some(emitter), Some(spatialized)) => {
+                    let gain = spatialized.gain;
+                    let gains = match emitter.panning {
+                        Panning::Stereo => {
+                            let [left, right] = pan_gains(sound.pan_law, spatialized.pan);
+
+                            [left * gain, right * gain]
+                        }
+                        // direction is rendered by the HRTF
+                        Panning::Hrtf => [gain; 2],
+                    };
 
-            loop {
-                let samples = sound.get_samples(remainder);
+                    (sound.spatial_gains.replace(gains).unwrap_or(gains), gains)
+                }
+                _ => {
+                    let gains = pan_gains(sound.pan_law, sound.pan);
 
-                for (b, s) in buffer.iter_mut().zip(samples) {
-                    *b += s * volume;
+                    (gains, gains)
                 }
+            };
 
-                remainder -= samples.len();
+            // occlusion and obstruction are smoothed per buffer, their gains ramped per frame
+            let (from_occlusion, _) = spatial::occlusion(sound.occlusion.value);
+            let (from_obstruction, _) = spatial::occlusion(sound.obstruction.value);
+            sound
+                .occlusion
+                .advance_over(frames, spatial::OCCLUSION_TIME);
+            sound
+                .obstruction
+                .advance_over(frames, spatial::OCCLUSION_TIME);
+            let (to_occlusion, occlusion_cutoff) = spatial::occlusion(sound.occlusion.value);
+            let (to_obstruction, obstruction_cutoff) = spatial::occlusion(sound.obstruction.value);
+            let from_gains = from_gains.map(|gain| gain * from_occlusion);
+            let to_gains = to_gains.map(|gain| gain * to_occlusion);
 
-                if remainder > 0 && sound.looped {
-                    sound.rewind();
-                    continue;
+            if let Some(decoder) = &mut sound.ambisonic {
+                // no position to mix listeners by, the first one turns the soundfield
+                let listener = self.listeners.first().copied().unwrap_or_default();
+
+                decoder.set_rotation(ambisonic::rotation(&listener));
+            }
+
+            let mut finished = false;
+
+            self.scratch.clear();
+            self.scratch.resize(buffer.len(), 0.0);
+
+            for (n, out) in self.scratch.chunks_exact_mut(2).enumerate() {
+                let t = n as f32 / frames as f32;
+                let left_gain = from_gains[0] + (to_gains[0] - from_gains[0]) * t;
+                let right_gain = from_gains[1] + (to_gains[1] - from_gains[1]) * t;
+                sound.doppler = from_doppler + (to_doppler - from_doppler) * t;
+
+                if sound.stopping && sound.stop_gain.is_finished() {
+                    finished = true;
+                    break;
+                }
+                // faded out, keep the position where the pause started
+                if sound.is_faded_out() {
+                    break;
+                }
+
+                let volume = sound.volume.next() * sound.stop_gain.next() * sound.pause_gain.next();
+
+                match sound.next_frame() {
+                    Some(frame) => {
+                        let [left, right] = match &mut sound.ambisonic {
+                            Some(decoder) => decoder.decode(
+                                t,
+                                self.ambisonic_panning,
+                                &self.ambisonic_filters,
+                                frame,
+                            ),
+                            None => [frame[0], frame[1]],
+                        };
+
+                        out[0] = left * left_gain * volume;
+                        out[1] = right * right_gain * volume;
+                    }
+                    None => {
+                        finished = true;
+                        break;
+                    }
+                }
+            }
+
+            let cutoff = spatialized
+                .map_or(spatial::OPEN_CUTOFF, |s| s.cutoff)
+                .min(occlusion_cutoff);
+            sound
+                .filter
+                .process(cutoff, &mut self.scratch, &mut self.filter_dry);
+
+            if let (Some(emitter), Some(spatialized)) = (sound.emitter, spatialized) {
+                if emitter.panning == Panning::Hrtf {
+                    sound.hrtf.get_or_insert_with(HrtfPanner::new).process(
+                        &self.hrtf,
+                        spatialized.direction,
+                        &mut self.scratch,
+                    );
+                }
+            }
+
+            if !sound.effects.is_empty() {
+                sound.effects.process(&mut self.scratch);
+            }
+
+            for &(bus, level) in &sound.sends {
+                let bus = self.buses.get_mut(&bus).unwrap();
+
+                for (out, sample) in bus.voices.iter_mut().zip(&self.scratch) {
+                    *out += sample * level;
+                }
+            }
+
+            // obstruction only applies to the direct path, after the sends
+            sound.obstruction_filter.process(
+                obstruction_cutoff,
+                &mut self.scratch,
+                &mut self.filter_dry,
+            );
+            if from_obstruction != 1.0 || to_obstruction != 1.0 {
+                for (n, out) in self.scratch.chunks_exact_mut(2).enumerate() {
+                    let t = n as f32 / frames as f32;
+                    let gain = from_obstruction + (to_obstruction - from_obstruction) * t;
+
+                    out[0] *= gain;
+                    out[1] *= gain;
                 }
+            }
 
-                break;
+            let bus = self.buses.get_mut(&sound.bus).unwrap();
+            for (out, sample) in bus.voices.iter_mut().zip(&self.scratch) {
+                *out += sample;
             }
 
-            if remainder > 0 {
-                self.mixer_state.swap_remove(i);
+            if finished {
+                self.remove_playback(i);
             } else {
+                sound
+                    .state
+                    .frame
+                    .store(sound.position as usize, Ordering::Relaxed);
+                sound
+                    .state
+                    .rate
+                    .store((sound.pitch * sound.doppler).to_bits(), Ordering::Relaxed);
                 i += 1;
             }
         }
+
+        for &id in &self.bus_order {
+            let bus = self.buses.get_mut(&id).unwrap();
+
+            // bus output goes to `voices`, to avoid one more buffer
+            for (out, children) in bus
+                .voices
+                .chunks_exact_mut(2)
+                .zip(bus.children.chunks_exact(2))
+            {
+                let solo_gain = bus.solo_gain.next();
+
+                out[0] = out[0] * solo_gain + children[0];
+                out[1] = out[1] * solo_gain + children[1];
+            }
+
+            if !bus.effects.is_empty() {
+                bus.effects.process(&mut bus.voices);
+            }
+
+            for out in bus.voices.chunks_exact_mut(2) {
+                let gain = bus.gain.next();
+
+                out[0] *= gain;
+                out[1] *= gain;
+            }
+
+            if let Some(key) = bus.ducker.as_ref().map(|ducker| ducker.key) {
+                if let Some(key_bus) = self.buses.get_mut(&key).filter(|_| key != id) {
+                    let key_output = std::mem::take(&mut key_bus.voices);
+                    let bus = self.buses.get_mut(&id).unwrap();
+
+                    bus.ducker
+                        .as_mut()
+                        .unwrap()
+                        .process(&mut bus.voices, &key_output);
+                    self.buses.get_mut(&key).unwrap().voices = key_output;
+                }
+            }
+            let bus = self.buses.get_mut(&id).unwrap();
+
+            if id == Bus::MASTER.id {
+                buffer.copy_from_slice(&bus.voices);
+
+                if let Some(limiter) = &mut self.limiter {
+                    limiter.process(buffer);
+                }
+                continue;
+            }
+
+            let output = std::mem::take(&mut bus.voices);
+            let parent = bus.parent;
+
+            for (out, sample) in self
+                .buses
+                .get_mut(&parent)
+                .unwrap()
+                .children
+                .iter_mut()
+                .zip(&output)
+            {
+                *out += sample;
+            }
+
+            self.buses.get_mut(&id).unwrap().voices = output;
+        }
+    }
+}
+
+/// Left and right channel gains for a given pan, 1.0 for both when centered.
+fn pan_gains(law: PanLaw, pan: f32) -> [f32; 2] {
+    let pan = pan.clamp(-1.0, 1.0);
+
+    match law {
+        PanLaw::ConstantPower => {
+            let angle = (pan + 1.0) * std::f32::consts::FRAC_PI_4;
+
+            [
+                std::f32::consts::SQRT_2 * angle.cos(),
+                std::f32::consts::SQRT_2 * angle.sin(),
+            ]
+        }
+        PanLaw::Linear => [(1.0 - pan).min(1.0), (1.0 + pan).min(1.0)],
     }
 }
 
+fn seconds_to_frames(seconds: f32) -> usize {
+    (seconds.max(0.0) * SAMPLE_RATE as f32) as usize
+}
+
 /// Parse ogg/wav/etc and get  resampled to 44100, 2 channel data
 pub fn load_samples_from_file(bytes: &[u8]) -> Result<Vec<f32>, ()> {
+    let (frames, channels_count) = decode_file(bytes);
+    assert!(
+        channels_count == 1 || channels_count == 2,
+        "only mono and stereo files can be loaded as sounds, see Sound::load_ambisonic"
+    );
+
+    // audrey's frame docs: "TODO: Should consider changing this behaviour to check the audio file's actual number of channels and automatically convert to F's number of channels while reading".
+    // lets fix this TODO here
+    if channels_count == 1 {
+        return Ok(frames.iter().flat_map(|&sample| [sample, sample]).collect());
+    }
+
+    Ok(frames)
+}
+
+/// Parse a first order ambisonic ogg/wav/etc and get it resampled to 44100, 4 channel AmbiX data
+pub fn load_ambisonic_samples_from_file(
+    bytes: &[u8],
+    format: AmbisonicFormat,
+) -> Result<Vec<f32>, ()> {
+    let (mut frames, channels_count) = decode_file(bytes);
+    assert!(
+        channels_count == 4,
+        "first order ambisonics should have 4 channels"
+    );
+
+    format.to_ambix(&mut frames);
+
+    Ok(frames)
+}
+
+/// Parse ogg/wav/etc and get it resampled to 44100, with its own channels count
+fn decode_file(bytes: &[u8]) -> (Vec<f32>, usize) {
     let mut audio_stream = {
         let file = std::io::Cursor::new(bytes);
         audrey::Reader::new(file).unwrap()
     };
 
     let description = audio_stream.description();
-    let channels_count = description.channel_count();
-    assert!(channels_count == 1 || channels_count == 2);
+    let channels_count = description.channel_count() as usize;
 
-    let mut frames: Vec<f32> = Vec::with_capacity(4096);
-    let mut samples_iterator = audio_stream
+    let frames: Vec<f32> = audio_stream
         .samples::<f32>()
-        .map(std::result::Result::unwrap);
-
-    // audrey's frame docs: "TODO: Should consider changing this behaviour to check the audio file's actual number of channels and automatically convert to F's number of channels while reading".
-    // lets fix this TODO here
-    if channels_count == 1 {
-        frames.extend(samples_iterator.flat_map(|sample| [sample, sample]));
-    } else if channels_count == 2 {
-        frames.extend(samples_iterator);
-    }
+        .map(std::result::Result::unwrap)
+        .collect();
 
     let sample_rate = description.sample_rate();
 
     // stupid nearest-neighbor resampler
-    if sample_rate != 44100 {
-        let mut new_length = ((44100 as f32 / sample_rate as f32) * frames.len() as f32) as usize;
+    if sample_rate != SAMPLE_RATE {
+        let mut new_length =
+            ((SAMPLE_RATE as f32 / sample_rate as f32) * frames.len() as f32) as usize;
 
-        // `new_length` must be an even number
-        new_length -= new_length % 2;
+        // `new_length` must be a whole number of frames
+        new_length -= new_length % channels_count;
 
         let mut resampled = vec![0.0; new_length];
 
-        for (n, sample) in resampled.chunks_exact_mut(2).enumerate() {
-            let ix = 2 * ((n as f32 / new_length as f32) * frames.len() as f32) as usize;
-            sample[0] = frames[ix];
-            sample[1] = frames[ix + 1];
+        for (n, sample) in resampled.chunks_exact_mut(channels_count).enumerate() {
+            let ix =
+                channels_count * ((n as f32 / new_length as f32) * frames.len() as f32) as usize;
+            sample.copy_from_slice(&frames[ix..ix + channels_count]);
         }
-        return Ok(resampled);
+        return (resampled, channels_count);
     }
 
-    Ok(frames)
+    (frames, channels_count)
 }
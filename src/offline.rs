@@ -0,0 +1,1459 @@
+//! Rendering the mixer into memory, without any audio device.
+
+use crate::{mixer::Mixer, AudioContext, SAMPLE_RATE};
+
+use std::io::{self, Write};
+
+/// An `AudioContext` that is not connected to an audio device.
+///
+/// Derefs to `AudioContext`, so `Sound::load`, `Sound::play`, `Playback::stop`
+/// etc. work exactly as with a real context. Nothing is heard until `render`
+/// is called: every call advances time by the requested number of frames,
+/// as fast as the CPU allows.
+///
+/// Control calls are applied at the start of the next `render`, so rendering
+/// in small chunks gives frame-accurate timing of a scripted sequence.
+pub struct OfflineContext {
+    ctx: AudioContext,
+    mixer: Mixer,
+    frames_rendered: u64,
+}
+
+impl OfflineContext {
+    pub fn new() -> OfflineContext {
+        let (mixer_builder, mixer_ctrl) = Mixer::new();
+
+        OfflineContext {
+            ctx: AudioContext::from_mixer_ctrl(mixer_ctrl),
+            mixer: mixer_builder.build(),
+            frames_rendered: 0,
+        }
+    }
+
+    /// Mix the next `frames` frames into a new interleaved stereo buffer.
+    pub fn render(&mut self, frames: usize) -> Vec<f32> {
+        let mut buffer = vec![0.0; frames * 2];
+
+        self.render_into(&mut buffer);
+
+        buffer
+    }
+
+    /// Mix the next `buffer.len() / 2` frames into an interleaved stereo buffer.
+    pub fn render_into(&mut self, buffer: &mut [f32]) {
+        assert!(
+            buffer.len() % 2 == 0,
+            "buffer should contain whole stereo frames"
+        );
+
+        let frames = buffer.len() / 2;
+
+        self.mixer.fill_audio_buffer(buffer, frames);
+        self.frames_rendered += frames as u64;
+    }
+
+    /// Total amount of frames rendered so far.
+    pub fn frames_rendered(&self) -> u64 {
+        self.frames_rendered
+    }
+
+    /// Time rendered so far, in seconds.
+    pub fn time(&self) -> f64 {
+        self.frames_rendered as f64 / SAMPLE_RATE as f64
+    }
+}
+
+impl std::ops::Deref for OfflineContext {
+    type Target = AudioContext;
+
+    fn deref(&self) -> &AudioContext {
+        &self.ctx
+    }
+}
+
+/// Write interleaved stereo samples, as produced by `OfflineContext::render`,
+/// as a 32-bit float .wav file.
+pub fn write_wav<W: Write>(mut writer: W, samples: &[f32]) -> io::Result<()> {
+    const CHANNELS: u16 = 2;
+    const BITS_PER_SAMPLE: u16 = 32;
+    const WAVE_FORMAT_IEEE_FLOAT: u16 = 3;
+
+    let block_align = CHANNELS * BITS_PER_SAMPLE / 8;
+    let data_size = (samples.len() * 4) as u32;
+
+    writer.write_all(b"RIFF")?;
+    writer.write_all(&(36 + data_size).to_le_bytes())?;
+    writer.write_all(b"WAVE")?;
+
+    writer.write_all(b"fmt ")?;
+    writer.write_all(&16u32.to_le_bytes())?;
+    writer.write_all(&WAVE_FORMAT_IEEE_FLOAT.to_le_bytes())?;
+    writer.write_all(&CHANNELS.to_le_bytes())?;
+    writer.write_all(&SAMPLE_RATE.to_le_bytes())?;
+    writer.write_all(&(SAMPLE_RATE * block_align as u32).to_le_bytes())?;
+    writer.write_all(&block_align.to_le_bytes())?;
+    writer.write_all(&BITS_PER_SAMPLE.to_le_bytes())?;
+
+    writer.write_all(b"data")?;
+    writer.write_all(&data_size.to_le_bytes())?;
+    for sample in samples {
+        writer.write_all(&sample.to_le_bytes())?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        ambisonic::AmbisonicFormat,
+        effects::{
+            AudioEffect, BiquadFilter, Compressor, CompressorSettings, Convolver, Delay,
+            DelaySettings, DelayTime, Detection, ImpulseResponse, Reverb, ReverbSettings,
+        },
+        limiter::db_to_gain,
+        mixer::load_samples_from_file,
+        Bus, Cone, DistanceModel, Ducking, Emitter, FadeCurve, HrtfSet, Limiter, Listener,
+        ListenerMix, PanLaw, Panning, PlaySoundParams, ReverbZone, Sound, ZoneShape,
+    };
+
+    use std::sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    };
+
+    /// Load interleaved stereo samples through a .wav in memory, as a game would.
+    fn sound(ctx: &OfflineContext, samples: &[f32]) -> Sound {
+        let mut wav = vec![];
+        write_wav(&mut wav, samples).unwrap();
+
+        Sound::load(ctx, &wav)
+    }
+
+    /// Both channels of every frame hold its index, scaled down, to tell frames apart.
+    fn frame_indices(frames: usize) -> Vec<f32> {
+        (0..frames * 2).map(|i| (i / 2) as f32 * 1e-4).collect()
+    }
+
+    fn looped() -> PlaySoundParams {
+        PlaySoundParams {
+            looped: true,
+            ..Default::default()
+        }
+    }
+
+    fn assert_close(a: f32, b: f32) {
+        assert!((a - b).abs() < 1e-4, "{} != {}", a, b);
+    }
+
+    #[test]
+    fn write_wav_round_trip() {
+        let samples: Vec<f32> = (0..2000).map(|i| (i as f32 * 0.01).sin()).collect();
+        let mut wav = vec![];
+        write_wav(&mut wav, &samples).unwrap();
+
+        assert_eq!(wav.len(), 44 + samples.len() * 4);
+        assert_eq!(load_samples_from_file(&wav).unwrap(), samples);
+    }
+
+    #[test]
+    fn render_mixes_playbacks() {
+        let mut ctx = OfflineContext::new();
+        let samples: Vec<f32> = (0..2000).map(|i| i as f32 / 2000.0).collect();
+        sound(&ctx, &samples).play(
+            &ctx,
+            PlaySoundParams {
+                looped: false,
+                volume: 0.5,
+                ..Default::default()
+            },
+        );
+
+        let mut output = ctx.render(400);
+        output.extend(ctx.render(600));
+        assert_eq!(ctx.frames_rendered(), 1000);
+        assert!((ctx.time() - 1000.0 / SAMPLE_RATE as f64).abs() < 1e-9);
+
+        for (&output, &sample) in output.iter().zip(&samples) {
+            assert_close(output, sample * 0.5);
+        }
+        assert!(ctx.render(100).iter().all(|&sample| sample == 0.0));
+    }
+
+    #[test]
+    fn non_looped_playback_ends() {
+        let mut ctx = OfflineContext::new();
+        let sound = sound(&ctx, &vec![0.5; 1000 * 2]);
+        assert_close(sound.duration(&ctx), 1000.0 / SAMPLE_RATE as f32);
+
+        let playback = sound.play(&ctx, Default::default());
+        ctx.render(512);
+        assert!(playback.is_playing());
+        assert_close(playback.position(), 512.0 / SAMPLE_RATE as f32);
+        assert_close(playback.remaining(), 488.0 / SAMPLE_RATE as f32);
+
+        let output = ctx.render(1024);
+        assert!(!playback.is_playing());
+        assert!(output[488 * 2..].iter().all(|&sample| sample == 0.0));
+    }
+
+    #[test]
+    fn looped_playback_wraps_until_stopped() {
+        let mut ctx = OfflineContext::new();
+        let sound = sound(&ctx, &vec![0.5; 1000 * 2]);
+
+        let playback = sound.play(&ctx, looped());
+        ctx.render(1500);
+        assert!(playback.is_playing());
+        assert_close(playback.position(), 500.0 / SAMPLE_RATE as f32);
+
+        sound.stop(&ctx);
+        ctx.render(512);
+        assert!(!playback.is_playing());
+        assert!(ctx.render(512).iter().all(|&sample| sample == 0.0));
+    }
+
+    #[test]
+    fn start_offset_and_seek() {
+        let mut ctx = OfflineContext::new();
+        let sound = sound(&ctx, &frame_indices(30000));
+
+        let playback = sound.play(
+            &ctx,
+            PlaySoundParams {
+                start_offset: 0.5,
+                ..Default::default()
+            },
+        );
+        let output = ctx.render(100);
+        assert!((output[0] - 22050.0 * 1e-4).abs() < 1e-5);
+
+        playback.seek(&ctx, 0.25);
+        let output = ctx.render(100);
+        assert!((output[0] - 11025.0 * 1e-4).abs() < 1e-5);
+        assert_close(playback.position(), (11025.0 + 100.0) / SAMPLE_RATE as f32);
+
+        // to the end, the playback is over and its position stays there
+        playback.seek(&ctx, 10.0);
+        ctx.render(100);
+        assert!(!playback.is_playing());
+        assert_close(playback.position(), sound.duration(&ctx));
+    }
+
+    /// Largest difference between consecutive left channel samples.
+    fn max_step(output: &[f32]) -> f32 {
+        output
+            .chunks_exact(2)
+            .zip(output.chunks_exact(2).skip(1))
+            .map(|(a, b)| (b[0] - a[0]).abs())
+            .fold(0.0, f32::max)
+    }
+
+    #[test]
+    fn pause_and_resume_without_clicks() {
+        let mut ctx = OfflineContext::new();
+        let sound = sound(&ctx, &vec![0.5; 10000 * 2]);
+
+        let playback = sound.play(&ctx, looped());
+        ctx.render(512);
+
+        playback.pause(&ctx);
+        let output = ctx.render(512);
+        assert!(playback.is_paused());
+        assert!(max_step(&output) < 0.01);
+        assert!(output[300 * 2..].iter().all(|&sample| sample == 0.0));
+
+        let position = playback.position();
+        assert!(ctx.render(512).iter().all(|&sample| sample == 0.0));
+        assert_close(playback.position(), position);
+
+        playback.resume(&ctx);
+        let output = ctx.render(512);
+        assert!(!playback.is_paused());
+        assert!(max_step(&output) < 0.01);
+        assert_close(output[output.len() - 1], 0.5);
+        assert!(playback.position() > position);
+
+        sound.pause_all(&ctx);
+        ctx.render(512);
+        assert!(ctx.render(512).iter().all(|&sample| sample == 0.0));
+        sound.resume_all(&ctx);
+        ctx.render(512);
+        assert_close(ctx.render(512)[0], 0.5);
+    }
+
+    #[test]
+    fn pitch_changes_playback_speed() {
+        let mut ctx = OfflineContext::new();
+        let sound = sound(&ctx, &frame_indices(2000));
+
+        let playback = sound.play(
+            &ctx,
+            PlaySoundParams {
+                pitch: 2.0,
+                ..Default::default()
+            },
+        );
+        let output = ctx.render(100);
+        for frame in 0..100 {
+            assert!((output[frame * 2] - (frame * 2) as f32 * 1e-4).abs() < 1e-5);
+        }
+
+        // fractional positions are interpolated
+        playback.set_pitch(&ctx, 0.5);
+        let output = ctx.render(100);
+        for frame in 0..100 {
+            let expected = 200.0 + frame as f32 * 0.5;
+            assert!((output[frame * 2] - expected * 1e-4).abs() < 1e-5);
+        }
+
+        // 1750 frames left, at twice the speed
+        playback.set_pitch(&ctx, 2.0);
+        ctx.render(800);
+        assert!(playback.is_playing());
+        assert_close(playback.remaining(), 150.0 / SAMPLE_RATE as f32 / 2.0);
+        ctx.render(100);
+        assert!(!playback.is_playing());
+    }
+
+    #[test]
+    fn pan_laws() {
+        let mut ctx = OfflineContext::new();
+        let sound = sound(&ctx, &vec![0.5; 1000 * 2]);
+
+        let playback = sound.play(
+            &ctx,
+            PlaySoundParams {
+                pan: -1.0,
+                ..looped()
+            },
+        );
+        let output = ctx.render(16);
+        assert_close(output[0], 0.5 * std::f32::consts::SQRT_2);
+        assert_close(output[1], 0.0);
+
+        // constant power: the same total power anywhere
+        playback.set_pan(&ctx, 0.5);
+        let output = ctx.render(16);
+        assert!(output[0] < output[1]);
+        assert_close(output[0] * output[0] + output[1] * output[1], 0.5);
+        playback.stop(&ctx);
+        ctx.render(1024);
+
+        sound.play(
+            &ctx,
+            PlaySoundParams {
+                pan: 0.5,
+                pan_law: PanLaw::Linear,
+                ..looped()
+            },
+        );
+        let output = ctx.render(16);
+        assert_close(output[0], 0.25);
+        assert_close(output[1], 0.5);
+    }
+
+    #[test]
+    fn fade_to_reaches_target() {
+        let mut ctx = OfflineContext::new();
+        let sound = sound(&ctx, &vec![0.5; 1000 * 2]);
+
+        for &curve in &[FadeCurve::Linear, FadeCurve::Exponential, FadeCurve::SCurve] {
+            let playback = sound.play(&ctx, looped());
+            playback.fade_to(&ctx, 0.25, 0.01, curve);
+
+            let output = ctx.render(1024);
+            assert!(max_step(&output) < 0.01, "{:?}", curve);
+            assert_close(output[output.len() - 1], 0.125);
+
+            playback.stop(&ctx);
+            ctx.render(1024);
+        }
+    }
+
+    #[test]
+    fn fade_in_and_stop_with_fade() {
+        let mut ctx = OfflineContext::new();
+        let sound = sound(&ctx, &vec![0.5; 1000 * 2]);
+
+        let playback = sound.play(
+            &ctx,
+            PlaySoundParams {
+                fade_in: 0.01,
+                ..looped()
+            },
+        );
+        let output = ctx.render(1024);
+        assert_close(output[0], 0.0);
+        assert!(max_step(&output) < 0.01);
+        assert_close(output[output.len() - 1], 0.5);
+
+        playback.stop_with_fade(&ctx, 0.01, FadeCurve::Linear);
+        let output = ctx.render(1024);
+        assert!(max_step(&output) < 0.01);
+        assert!(output[441 * 2..].iter().all(|&sample| sample == 0.0));
+        assert!(ctx.render(1024).iter().all(|&sample| sample == 0.0));
+    }
+
+    #[test]
+    fn volume_changes_keep_the_stop_fade() {
+        let mut ctx = OfflineContext::new();
+        let sound = sound(&ctx, &vec![0.5; 1000 * 2]);
+
+        // halfway through a 0.1s fade out, the volume of every playback of the sound changes
+        sound
+            .play(&ctx, looped())
+            .stop_with_fade(&ctx, 0.1, FadeCurve::Linear);
+        ctx.render(2205);
+        sound.set_volume(&ctx, 0.8);
+
+        let output = ctx.render(1024);
+        assert!(max_step(&output) < 0.01);
+        assert!(output[output.len() - 1] > 0.1);
+
+        let output = ctx.render(2205);
+        assert!(max_step(&output) < 0.01);
+        assert!(ctx.render(16).iter().all(|&sample| sample == 0.0));
+    }
+
+    #[test]
+    fn crossfade_keeps_power_constant() {
+        let mut ctx = OfflineContext::new();
+        let left = sound(&ctx, &[0.5, 0.0].repeat(1000));
+        let right = sound(&ctx, &[0.0, 0.5].repeat(1000));
+
+        let from = left.play(&ctx, looped());
+        ctx.render(16);
+        let to = right.crossfade(&ctx, from, looped(), 0.01);
+
+        // each playback on its own channel, their gains squared sum to one throughout
+        let output = ctx.render(1024);
+        for frame in output.chunks_exact(2) {
+            let (a, b) = (frame[0] / 0.5, frame[1] / 0.5);
+            assert!((a * a + b * b - 1.0).abs() < 1e-3, "{} {}", a, b);
+        }
+        assert_close(output[output.len() - 2], 0.0);
+        assert_close(output[output.len() - 1], 0.5);
+        assert!(to.is_playing());
+    }
+
+    #[test]
+    fn volume_changes_during_a_crossfade() {
+        let mut ctx = OfflineContext::new();
+        let left = sound(&ctx, &[0.5, 0.0].repeat(1000));
+        let right = sound(&ctx, &[0.0, 0.5].repeat(1000));
+
+        let from = left.play(&ctx, looped());
+        ctx.render(16);
+        right.crossfade(&ctx, from, looped(), 0.1);
+        ctx.render(2205);
+
+        // the playback fading out keeps fading from its new volume, it is not cut
+        left.set_volume(&ctx, 0.8);
+        let output = ctx.render(1024);
+        assert!(max_step(&output) < 0.01);
+        assert!(output[output.len() - 2] > 0.1);
+
+        ctx.render(2205);
+        let output = ctx.render(16);
+        assert_close(output[0], 0.0);
+        assert_close(output[1], 0.5);
+    }
+
+    #[test]
+    fn bus_volume_mute_and_solo() {
+        let mut ctx = OfflineContext::new();
+        let music = Bus::new(&ctx, "music", Bus::MASTER);
+        let drums = Bus::new(&ctx, "drums", music);
+        let sfx = Bus::new(&ctx, "sfx", Bus::MASTER);
+        assert_eq!(Bus::find(&ctx, "Master"), Some(Bus::MASTER));
+        assert_eq!(Bus::find(&ctx, "drums"), Some(drums));
+
+        // drums on the left channel, sfx on the right
+        let left = sound(&ctx, &[0.5, 0.0].repeat(1000));
+        let right = sound(&ctx, &[0.0, 0.5].repeat(1000));
+        left.play(
+            &ctx,
+            PlaySoundParams {
+                bus: drums,
+                ..looped()
+            },
+        );
+        right.play(
+            &ctx,
+            PlaySoundParams {
+                bus: sfx,
+                ..looped()
+            },
+        );
+
+        // the volume of a parent bus applies to its children
+        music.set_volume(&ctx, 0.5);
+        let output = ctx.render(1024);
+        assert!(max_step(&output) < 0.01);
+        assert_close(output[output.len() - 2], 0.25);
+        assert_close(output[output.len() - 1], 0.5);
+
+        sfx.set_muted(&ctx, true);
+        let output = ctx.render(1024);
+        assert_close(output[output.len() - 2], 0.25);
+        assert_close(output[output.len() - 1], 0.0);
+        sfx.set_muted(&ctx, false);
+
+        // a soloed bus is heard with its children, everything else is silenced
+        music.set_solo(&ctx, true);
+        let output = ctx.render(1024);
+        assert_close(output[output.len() - 2], 0.25);
+        assert_close(output[output.len() - 1], 0.0);
+
+        music.set_solo(&ctx, false);
+        let output = ctx.render(1024);
+        assert_close(output[output.len() - 2], 0.25);
+        assert_close(output[output.len() - 1], 0.5);
+    }
+
+    #[test]
+    fn limiter_keeps_peaks_below_ceiling() {
+        let mut ctx = OfflineContext::new();
+        let limiter = Limiter::default();
+        ctx.set_limiter(Some(limiter));
+        let ceiling = db_to_gain(limiter.ceiling);
+        let lookahead = (limiter.lookahead * SAMPLE_RATE as f32) as usize;
+
+        // a quiet part, then a loud one well over 0dBFS
+        let samples: Vec<f32> = (0..8000)
+            .map(|i| {
+                let level = if i < 4000 { 0.25 } else { 2.0 };
+                level * (i as f32 * 0.05).sin()
+            })
+            .collect();
+        sound(&ctx, &samples).play(&ctx, looped());
+
+        // the output is delayed by the lookahead, quiet parts go through untouched
+        let output = ctx.render(20000);
+        assert!(output[..lookahead * 2].iter().all(|&sample| sample == 0.0));
+        for (&output, &sample) in output[lookahead * 2..].iter().zip(&samples[..3000]) {
+            assert_close(output, sample);
+        }
+        assert!(output.iter().all(|sample| sample.abs() <= ceiling));
+    }
+
+    /// Multiplies the signal by parameter 0.
+    struct Gain(f32);
+
+    impl AudioEffect for Gain {
+        fn process(&mut self, buffer: &mut [f32]) {
+            for sample in buffer {
+                *sample *= self.0;
+            }
+        }
+
+        fn reset(&mut self) {}
+
+        fn set_parameter(&mut self, index: u32, value: f32) {
+            if index == 0 {
+                self.0 = value;
+            }
+        }
+    }
+
+    /// Counts its drops.
+    struct Tracked(Arc<AtomicUsize>);
+
+    impl AudioEffect for Tracked {
+        fn process(&mut self, _buffer: &mut [f32]) {}
+
+        fn reset(&mut self) {}
+    }
+
+    impl Drop for Tracked {
+        fn drop(&mut self) {
+            self.0.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    #[test]
+    fn garbage_is_dropped_on_the_control_side() {
+        let mut ctx = OfflineContext::new();
+        let drops = Arc::new(AtomicUsize::new(0));
+        let dropped = || drops.load(Ordering::Relaxed);
+
+        // a removed effect
+        let effect = Bus::MASTER.add_effect(&ctx, Tracked(drops.clone()));
+        effect.remove(&ctx);
+        ctx.render(16);
+        assert_eq!(dropped(), 0);
+        ctx.set_listener(Listener::default());
+        assert_eq!(dropped(), 1);
+
+        // the effect chain of a finished playback
+        let playback = sound(&ctx, &[0.5; 8 * 2]).play(&ctx, Default::default());
+        playback.add_effect(&ctx, Tracked(drops.clone()));
+        ctx.render(16);
+        assert!(!playback.is_playing());
+        assert_eq!(dropped(), 1);
+        ctx.set_listener(Listener::default());
+        assert_eq!(dropped(), 2);
+    }
+
+    #[test]
+    fn effect_chains() {
+        let mut ctx = OfflineContext::new();
+        let sound = sound(&ctx, &vec![0.5; 1000 * 2]);
+
+        let playback = sound.play(&ctx, looped());
+        let effect = playback.add_effect(&ctx, Gain(2.0));
+        assert_close(ctx.render(16)[0], 1.0);
+
+        effect.set_parameter(&ctx, 0, 0.5);
+        assert_close(ctx.render(16)[0], 0.25);
+
+        // wet/dry changes are ramped
+        effect.set_mix(&ctx, 0.5);
+        let output = ctx.render(1024);
+        assert!(max_step(&output) < 0.01);
+        assert_close(output[output.len() - 1], 0.375);
+
+        effect.set_bypass(&ctx, true);
+        let output = ctx.render(1024);
+        assert!(max_step(&output) < 0.01);
+        assert_close(output[output.len() - 1], 0.5);
+
+        // effects are applied one after another, bus effects after the playback ones
+        effect.set_bypass(&ctx, false);
+        let master = Bus::MASTER.add_effect(&ctx, Gain(0.5));
+        let output = ctx.render(1024);
+        assert_close(output[output.len() - 1], 0.1875);
+
+        master.remove(&ctx);
+        effect.remove(&ctx);
+        assert_close(ctx.render(16)[0], 0.5);
+    }
+
+    /// Stereo sine at `frequency` Hz, with a whole number of periods for looping.
+    fn sine(frequency: f32, frames: usize) -> Vec<f32> {
+        (0..frames * 2)
+            .map(|i| {
+                let t = (i / 2) as f32 / SAMPLE_RATE as f32;
+                0.5 * (t * frequency * 2.0 * std::f32::consts::PI).sin()
+            })
+            .collect()
+    }
+
+    fn peak(output: &[f32]) -> f32 {
+        output
+            .iter()
+            .fold(0.0, |peak, sample| sample.abs().max(peak))
+    }
+
+    /// Peak of a looped `sine` through `filter`, once the filter settled.
+    fn filtered_peak(frequency: f32, filter: BiquadFilter) -> f32 {
+        let mut ctx = OfflineContext::new();
+        let playback = sound(&ctx, &sine(frequency, 4410)).play(&ctx, looped());
+        playback.add_effect(&ctx, filter);
+
+        ctx.render(4410);
+        peak(&ctx.render(4410))
+    }
+
+    #[test]
+    fn biquad_filters() {
+        assert!((filtered_peak(100.0, BiquadFilter::low_pass(1000.0)) - 0.5).abs() < 0.01);
+        assert!(filtered_peak(10000.0, BiquadFilter::low_pass(1000.0)) < 0.01);
+
+        assert!(filtered_peak(100.0, BiquadFilter::high_pass(5000.0)) < 0.01);
+        assert!((filtered_peak(10000.0, BiquadFilter::high_pass(1000.0)) - 0.5).abs() < 0.01);
+
+        // -12dB below the shelf, untouched above
+        let low_shelf = || BiquadFilter::low_shelf(1000.0, -12.0);
+        assert!((filtered_peak(100.0, low_shelf()) - 0.5 * db_to_gain(-12.0)).abs() < 0.01);
+        assert!((filtered_peak(10000.0, low_shelf()) - 0.5).abs() < 0.01);
+
+        // the cutoff glides up, the high sine comes through
+        let mut ctx = OfflineContext::new();
+        let playback = sound(&ctx, &sine(10000.0, 4410)).play(&ctx, looped());
+        let filter = playback.add_effect(&ctx, BiquadFilter::low_pass(1000.0));
+        ctx.render(4410);
+        filter.set_parameter(&ctx, BiquadFilter::CUTOFF, 20000.0);
+        ctx.render(4410);
+        assert!((peak(&ctx.render(4410)) - 0.5).abs() < 0.05);
+    }
+
+    #[test]
+    fn reverb_on_a_send_bus() {
+        let mut ctx = OfflineContext::new();
+        let reverb = Bus::new(&ctx, "reverb", Bus::MASTER);
+        reverb.add_effect(
+            &ctx,
+            Reverb::new(ReverbSettings {
+                dry: 0.0,
+                ..Default::default()
+            }),
+        );
+
+        // a 100 frames click, heard directly and through the send
+        sound(&ctx, &vec![0.5; 100 * 2]).play(
+            &ctx,
+            PlaySoundParams {
+                looped: false,
+                sends: vec![(reverb, 0.5)],
+                ..Default::default()
+            },
+        );
+        let output = ctx.render(SAMPLE_RATE as usize * 3);
+        assert_close(output[0], 0.5);
+
+        // nothing until the pre-delay and the shortest comb filter pass, then a decaying tail
+        let silence = (0.02 * SAMPLE_RATE as f32) as usize + 1116;
+        assert!(peak(&output[100 * 2..silence * 2]) < 1e-6);
+        let early = peak(&output[silence * 2..SAMPLE_RATE as usize * 2]);
+        let late = peak(&output[SAMPLE_RATE as usize * 4..]);
+        assert!(early > 0.01);
+        assert!(late < early * 0.01);
+    }
+
+    /// Largest second difference of the left channel, a jump in a smooth signal stands out.
+    fn max_curvature(output: &[f32]) -> f32 {
+        output
+            .chunks_exact(2)
+            .collect::<Vec<_>>()
+            .windows(3)
+            .map(|w| (w[2][0] - 2.0 * w[1][0] + w[0][0]).abs())
+            .fold(0.0, f32::max)
+    }
+
+    #[test]
+    fn reverb_pre_delay_glides() {
+        let mut ctx = OfflineContext::new();
+        let playback = sound(&ctx, &sine(110.0, 4410)).play(&ctx, looped());
+        let reverb = playback.add_effect(
+            &ctx,
+            Reverb::new(ReverbSettings {
+                room_size: 0.0,
+                dry: 0.0,
+                ..Default::default()
+            }),
+        );
+        let steady = max_curvature(&ctx.render(8820)[4410 * 2..]);
+
+        reverb.set_parameter(&ctx, Reverb::PRE_DELAY, 0.05);
+        // a jump of the read position would be a step in the output
+        assert!(max_curvature(&ctx.render(4410)) < steady * 4.0);
+    }
+
+    /// Deterministic noise in -1.0..1.0.
+    fn noise(len: usize, mut seed: u32) -> Vec<f32> {
+        (0..len)
+            .map(|_| {
+                seed = seed.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+                (seed >> 8) as f32 / (1 << 23) as f32 - 1.0
+            })
+            .collect()
+    }
+
+    #[test]
+    fn convolver_matches_direct_convolution() {
+        let mut ctx = OfflineContext::new();
+        // longer than one partition, to cover the frequency domain delay line
+        let response = noise(700 * 2, 2)
+            .iter()
+            .map(|sample| sample * 0.1)
+            .collect::<Vec<_>>();
+        let input = noise(4000 * 2, 3);
+
+        let convolver = Convolver::new(ImpulseResponse::from_samples(&response));
+        let latency = (convolver.latency_seconds() * SAMPLE_RATE as f32).round() as usize;
+        Bus::MASTER.add_effect(&ctx, convolver);
+        sound(&ctx, &input).play(&ctx, Default::default());
+
+        let output = ctx.render(4000);
+        for frame in latency..4000 {
+            for channel in 0..2 {
+                let expected: f32 = (0..700)
+                    .take_while(|&k| k + latency <= frame)
+                    .map(|k| response[k * 2 + channel] * input[(frame - latency - k) * 2 + channel])
+                    .sum();
+
+                assert!((output[frame * 2 + channel] - expected).abs() < 1e-3);
+            }
+        }
+    }
+
+    #[test]
+    fn convolver_swaps_impulse_response() {
+        let mut ctx = OfflineContext::new();
+        let convolver =
+            Convolver::with_max_duration(ImpulseResponse::from_samples(&[1.0, 1.0]), 0.1);
+        let latency_seconds = convolver.latency_seconds();
+        let latency = (latency_seconds * SAMPLE_RATE as f32).round() as usize;
+        let effect = Bus::MASTER.add_effect(&ctx, convolver);
+        assert_eq!(effect.latency_seconds(), latency_seconds);
+        sound(&ctx, &vec![0.5; 1000 * 2]).play(&ctx, looped());
+
+        let output = ctx.render(latency * 2);
+        assert_close(output[output.len() - 1], 0.5);
+
+        // a longer response, a quarter of the level after the crossfade
+        let mut response = vec![0.0; 2000 * 2];
+        response[1999 * 2] = 0.25;
+        response[1999 * 2 + 1] = 0.25;
+        effect.set_data(&ctx, ImpulseResponse::from_samples(&response));
+        ctx.render(latency * 6);
+        assert_close(ctx.render(16)[0], 0.125);
+
+        // past the 0.1s the convolver was created for, the response is cut short
+        let mut response = vec![0.0; 8000 * 2];
+        response[7999 * 2] = 0.25;
+        response[7999 * 2 + 1] = 0.25;
+        effect.set_data(&ctx, ImpulseResponse::from_samples(&response));
+        ctx.render(10000);
+        assert_close(ctx.render(16)[0], 0.0);
+    }
+
+    #[test]
+    fn delay_echoes() {
+        let settings = DelaySettings {
+            // 250ms, 11025 frames
+            time: DelayTime::Beats {
+                beats: 0.5,
+                bpm: 120.0,
+            },
+            feedback: 0.5,
+            damping: 0.0,
+            wet: 1.0,
+            dry: 0.0,
+            ..Default::default()
+        };
+
+        for &ping_pong in &[false, true] {
+            let mut ctx = OfflineContext::new();
+            let playback = sound(&ctx, &[0.5, 0.0]).play(&ctx, Default::default());
+            playback.add_effect(
+                &ctx,
+                Delay::new(DelaySettings {
+                    ping_pong,
+                    ..settings
+                }),
+            );
+
+            let output = ctx.render(30000);
+            let echoes: Vec<(usize, f32)> = output
+                .iter()
+                .enumerate()
+                .filter(|(_, sample)| sample.abs() > 1e-6)
+                .map(|(i, &sample)| (i, sample))
+                .collect();
+
+            if ping_pong {
+                // the input is summed to mono, then echoes bounce from the left to the right
+                assert_eq!(echoes.len(), 2);
+                assert_eq!(echoes[0].0, 11025 * 2);
+                assert_close(echoes[0].1, 0.25);
+                assert_eq!(echoes[1].0, 22050 * 2 + 1);
+                assert_close(echoes[1].1, 0.125);
+            } else {
+                assert_eq!(echoes.len(), 2);
+                assert_eq!(echoes[0].0, 11025 * 2);
+                assert_close(echoes[0].1, 0.5);
+                assert_eq!(echoes[1].0, 22050 * 2);
+                assert_close(echoes[1].1, 0.25);
+            }
+        }
+    }
+
+    #[test]
+    fn ducking_follows_key_bus() {
+        let mut ctx = OfflineContext::new();
+        let music = Bus::new(&ctx, "music", Bus::MASTER);
+        let voice = Bus::new(&ctx, "voice", Bus::MASTER);
+        music.set_ducking(
+            &ctx,
+            Some(Ducking {
+                amount: 12.0,
+                attack: 0.05,
+                release: 0.5,
+                ..Ducking::new(voice)
+            }),
+        );
+
+        // music on the left channel, a half second line of dialogue on the right
+        let left = sound(&ctx, &[0.5, 0.0].repeat(1000));
+        let right = sound(&ctx, &[0.0, 0.5].repeat(22050));
+        left.play(
+            &ctx,
+            PlaySoundParams {
+                bus: music,
+                ..looped()
+            },
+        );
+        assert_close(ctx.render(1024)[0], 0.5);
+
+        right.play(
+            &ctx,
+            PlaySoundParams {
+                looped: false,
+                bus: voice,
+                ..Default::default()
+            },
+        );
+        let output = ctx.render(4410);
+        assert!(max_step(&output) < 0.01);
+        assert_close(output[output.len() - 2], 0.5 * db_to_gain(-12.0));
+        assert_close(output[output.len() - 1], 0.5);
+
+        // the dialogue ends, music recovers within the key and ducking release times
+        ctx.render(22050 - 4410);
+        let output = ctx.render(SAMPLE_RATE as usize);
+        assert!(max_step(&output) < 0.01);
+        assert_close(output[output.len() - 2], 0.5);
+        assert_close(output[output.len() - 1], 0.0);
+    }
+
+    /// Settled level of a constant `level` signal through `compressor`.
+    fn compressed(level: f32, compressor: Compressor) -> f32 {
+        let mut ctx = OfflineContext::new();
+        let playback = sound(&ctx, &vec![level; 1000 * 2]).play(&ctx, looped());
+        playback.add_effect(&ctx, compressor);
+
+        ctx.render(SAMPLE_RATE as usize / 2);
+        ctx.render(16)[0]
+    }
+
+    #[test]
+    fn compressor_gain_reduction() {
+        let hard_knee = CompressorSettings {
+            threshold: -18.0,
+            ratio: 4.0,
+            knee: 0.0,
+            ..Default::default()
+        };
+        let level_db = 20.0 * 0.5f32.log10();
+        let expected = db_to_gain(level_db - (level_db + 18.0) * 0.75);
+
+        // below the threshold the signal is untouched
+        assert_close(compressed(0.05, Compressor::new(hard_knee)), 0.05);
+
+        // 4dB over the threshold become 1dB, with either detection
+        assert_close(compressed(0.5, Compressor::new(hard_knee)), expected);
+        let rms = CompressorSettings {
+            detection: Detection::Rms,
+            ..hard_knee
+        };
+        assert_close(compressed(0.5, Compressor::new(rms)), expected);
+
+        let makeup = CompressorSettings {
+            makeup: 6.0,
+            ..hard_knee
+        };
+        assert_close(
+            compressed(0.5, Compressor::new(makeup)),
+            expected * db_to_gain(6.0),
+        );
+    }
+
+    /// Settled left and right output of a constant 0.5 sound played from `emitter`.
+    fn spatialized(ctx: &mut OfflineContext, emitter: Emitter) -> (f32, f32) {
+        let playback = sound(ctx, &vec![0.5; 1000 * 2]).play(
+            ctx,
+            PlaySoundParams {
+                emitter: Some(emitter),
+                ..looped()
+            },
+        );
+        ctx.render(1024);
+        let output = ctx.render(16);
+        playback.stop(ctx);
+        ctx.render(1024);
+
+        (output[output.len() - 2], output[output.len() - 1])
+    }
+
+    #[test]
+    fn remaining_time_follows_doppler() {
+        let mut ctx = OfflineContext::new();
+        let playback = sound(&ctx, &vec![0.5; 44100 * 2]).play(
+            &ctx,
+            PlaySoundParams {
+                // approaching at a quarter of the speed of sound, 4/3 faster
+                emitter: Some(Emitter {
+                    position: [0.0, 0.0, -100.0],
+                    velocity: [0.0, 0.0, 343.3 / 4.0],
+                    ..Default::default()
+                }),
+                ..Default::default()
+            },
+        );
+        ctx.render(1024);
+        ctx.render(16);
+
+        // the rest of the 1s sound takes 3/4 of its duration to play
+        assert_close(playback.remaining(), (1.0 - playback.position()) * 0.75);
+    }
+
+    #[test]
+    fn distance_models() {
+        let mut ctx = OfflineContext::new();
+        let ahead = |distance: f32, distance_model: DistanceModel, rolloff: f32| Emitter {
+            position: [0.0, 0.0, -distance],
+            distance_model,
+            rolloff,
+            ..Default::default()
+        };
+
+        let cases = [
+            (ahead(0.5, DistanceModel::Inverse, 1.0), 1.0),
+            (ahead(4.0, DistanceModel::Inverse, 1.0), 0.25),
+            (ahead(50.5, DistanceModel::Linear, 1.0), 0.5),
+            (ahead(200.0, DistanceModel::Linear, 1.0), 0.0),
+            (ahead(2.0, DistanceModel::Exponential, 2.0), 0.25),
+            // no more attenuation past max_distance
+            (ahead(1000.0, DistanceModel::Inverse, 1.0), 0.01),
+        ];
+        for &(emitter, attenuation) in &cases {
+            let (left, right) = spatialized(&mut ctx, emitter);
+            assert_close(left, 0.5 * attenuation);
+            assert_close(right, 0.5 * attenuation);
+        }
+    }
+
+    #[test]
+    fn emitter_direction_pans() {
+        let mut ctx = OfflineContext::new();
+        let right_side = Emitter {
+            position: [1.0, 0.0, 0.0],
+            ..Default::default()
+        };
+        let (left, right) = spatialized(&mut ctx, right_side);
+        assert_close(left, 0.0);
+        assert_close(right, 0.5 * std::f32::consts::SQRT_2);
+
+        // turned around, the same emitter is on the left
+        ctx.set_listener(Listener {
+            forward: [0.0, 0.0, 1.0],
+            ..Default::default()
+        });
+        let (left, right) = spatialized(&mut ctx, right_side);
+        assert_close(left, 0.5 * std::f32::consts::SQRT_2);
+        assert_close(right, 0.0);
+
+        // moving emitters are followed
+        ctx.set_listener(Listener::default());
+        let playback = sound(&ctx, &vec![0.5; 1000 * 2]).play(
+            &ctx,
+            PlaySoundParams {
+                emitter: Some(right_side),
+                ..looped()
+            },
+        );
+        ctx.render(1024);
+        playback.set_position(&ctx, [0.0, 0.0, -2.0]);
+        assert!(max_step(&ctx.render(1024)) < 0.01);
+        let output = ctx.render(16);
+        assert_close(output[output.len() - 2], 0.25);
+        assert_close(output[output.len() - 1], 0.25);
+    }
+
+    #[test]
+    fn doppler_shifts_pitch() {
+        let mut ctx = OfflineContext::new();
+        let sound = sound(&ctx, &frame_indices(30000));
+        let emitter = Emitter {
+            position: [0.0, 0.0, -10.0],
+            min_distance: 10.0,
+            ..Default::default()
+        };
+        // playback speed from the difference between consecutive frames
+        let rate = |ctx: &mut OfflineContext| {
+            ctx.render(1024);
+            let output = ctx.render(16);
+            (output[2] - output[0]) / 1e-4
+        };
+
+        // approaching at a tenth of the speed of sound, then moving away
+        let playback = sound.play(
+            &ctx,
+            PlaySoundParams {
+                emitter: Some(Emitter {
+                    velocity: [0.0, 0.0, 34.33],
+                    ..emitter
+                }),
+                ..Default::default()
+            },
+        );
+        assert!((rate(&mut ctx) - 343.3 / (343.3 - 34.33)).abs() < 1e-3);
+
+        playback.set_velocity(&ctx, [0.0, 0.0, -34.33]);
+        assert!((rate(&mut ctx) - 343.3 / (343.3 + 34.33)).abs() < 1e-3);
+
+        // a moving listener shifts the pitch too
+        playback.set_velocity(&ctx, [0.0; 3]);
+        ctx.set_listener(Listener {
+            velocity: [0.0, 0.0, -34.33],
+            ..Default::default()
+        });
+        assert!((rate(&mut ctx) - (343.3 + 34.33) / 343.3).abs() < 1e-3);
+
+        ctx.set_doppler(343.3, 0.0);
+        assert!((rate(&mut ctx) - 1.0).abs() < 1e-3);
+    }
+
+    #[test]
+    fn hrtf_panning() {
+        let mut ctx = OfflineContext::new();
+        let right_side = Emitter {
+            position: [1.0, 0.0, 0.0],
+            panning: Panning::Hrtf,
+            ..Default::default()
+        };
+
+        // a click on the right reaches the right ear first and louder
+        sound(&ctx, &[0.5, 0.5]).play(
+            &ctx,
+            PlaySoundParams {
+                emitter: Some(right_side),
+                ..Default::default()
+            },
+        );
+        let output = ctx.render(1024);
+        let arrival = |ear: usize| {
+            output
+                .iter()
+                .skip(ear)
+                .step_by(2)
+                .position(|sample| sample.abs() > 1e-3)
+                .unwrap()
+        };
+        let energy =
+            |ear: usize| -> f32 { output.iter().skip(ear).step_by(2).map(|s| s * s).sum() };
+        assert!(arrival(1) < arrival(0));
+        assert!(energy(1) > energy(0) * 2.0);
+
+        // a measured set with the same response in every direction
+        let mut set = HrtfSet::new();
+        let mut impulse = vec![0.0; 64 * 2];
+        impulse[0] = 1.0;
+        impulse[1] = 0.5;
+        set.add_measurement(-90.0, 0.0, &impulse);
+        set.add_measurement(90.0, 0.0, &impulse);
+        ctx.set_hrtf(set);
+
+        sound(&ctx, &vec![0.5; 1000 * 2]).play(
+            &ctx,
+            PlaySoundParams {
+                emitter: Some(right_side),
+                ..looped()
+            },
+        );
+        ctx.render(1024);
+        let output = ctx.render(16);
+        assert_close(output[0], 0.5);
+        assert_close(output[1], 0.25);
+    }
+
+    #[test]
+    fn emitter_cones() {
+        let mut ctx = OfflineContext::new();
+        let emitter = |orientation: [f32; 3]| Emitter {
+            position: [0.0, 0.0, -1.0],
+            orientation,
+            cone: Cone {
+                inner_angle: 60.0,
+                outer_angle: 120.0,
+                outer_gain: 0.25,
+                outer_cutoff: 1000.0,
+            },
+            ..Default::default()
+        };
+        let facing = emitter([0.0, 0.0, 1.0]);
+        let sideways = emitter([0.5f32.sqrt(), 0.0, 0.5f32.sqrt()]);
+        let away = emitter([0.0, 0.0, -1.0]);
+
+        assert_close(spatialized(&mut ctx, facing).0, 0.5);
+        // halfway between the inner and the outer cone
+        assert_close(spatialized(&mut ctx, sideways).0, 0.5 * 0.625);
+        assert_close(spatialized(&mut ctx, away).0, 0.5 * 0.25);
+
+        // moving behind the listener brings the cone low-pass in without a click,
+        // moving back in front takes it out
+        let playback = sound(&ctx, &vec![0.5; 1000 * 2]).play(
+            &ctx,
+            PlaySoundParams {
+                emitter: Some(facing),
+                ..looped()
+            },
+        );
+        ctx.render(1024);
+        playback.set_position(&ctx, [0.0, 0.0, 1.0]);
+        assert!(max_step(&ctx.render(4410)) < 0.01);
+        playback.set_position(&ctx, [0.0, 0.0, -1.0]);
+        assert!(max_step(&ctx.render(4410)) < 0.01);
+        assert!(max_step(&ctx.render(4410)) < 0.01);
+        playback.stop(&ctx);
+        ctx.render(1024);
+
+        // outside of the outer cone high frequencies are filtered out
+        let playback = sound(&ctx, &sine(10000.0, 4410)).play(
+            &ctx,
+            PlaySoundParams {
+                emitter: Some(away),
+                ..looped()
+            },
+        );
+        ctx.render(4410);
+        assert!(peak(&ctx.render(4410)) < 0.01);
+
+        playback.set_emitter(&ctx, Some(facing));
+        ctx.render(4410);
+        assert!((peak(&ctx.render(4410)) - 0.5).abs() < 0.05);
+    }
+
+    /// Moves the left channel to the right one.
+    struct ToRight;
+
+    impl AudioEffect for ToRight {
+        fn process(&mut self, buffer: &mut [f32]) {
+            for frame in buffer.chunks_exact_mut(2) {
+                frame[1] = frame[0];
+                frame[0] = 0.0;
+            }
+        }
+
+        fn reset(&mut self) {}
+    }
+
+    #[test]
+    fn occlusion_and_obstruction() {
+        let mut ctx = OfflineContext::new();
+        // the direct sound on the left channel, the aux send on the right
+        let aux = Bus::new(&ctx, "aux", Bus::MASTER);
+        aux.add_effect(&ctx, ToRight);
+        let playback = sound(&ctx, &[0.5, 0.0].repeat(1000)).play(
+            &ctx,
+            PlaySoundParams {
+                sends: vec![(aux, 1.0)],
+                ..looped()
+            },
+        );
+        let output = ctx.render(1024);
+        assert_close(output[output.len() - 2], 0.5);
+        assert_close(output[output.len() - 1], 0.5);
+
+        // occlusion muffles everything, the low-pass comes in without a click
+        playback.set_occlusion(&ctx, 1.0);
+        assert!(max_step(&ctx.render(SAMPLE_RATE as usize)) < 0.01);
+        let output = ctx.render(16);
+        assert_close(output[output.len() - 2], 0.5 * db_to_gain(-18.0));
+        assert_close(output[output.len() - 1], 0.5 * db_to_gain(-18.0));
+
+        // obstruction only the direct sound
+        playback.set_occlusion(&ctx, 0.0);
+        playback.set_obstruction(&ctx, 1.0);
+        assert!(max_step(&ctx.render(SAMPLE_RATE as usize)) < 0.01);
+        let output = ctx.render(16);
+        assert_close(output[output.len() - 2], 0.5 * db_to_gain(-18.0));
+        assert_close(output[output.len() - 1], 0.5);
+        playback.set_obstruction(&ctx, 0.0);
+        assert!(max_step(&ctx.render(SAMPLE_RATE as usize)) < 0.01);
+
+        // and a half occluded playback is quieter and darker
+        playback.stop(&ctx);
+        ctx.render(1024);
+        let playback = sound(&ctx, &sine(10000.0, 4410)).play(&ctx, looped());
+        ctx.render(4410);
+        let open = peak(&ctx.render(4410));
+        playback.set_occlusion(&ctx, 0.5);
+        ctx.render(SAMPLE_RATE as usize);
+        assert!(peak(&ctx.render(4410)) < open * db_to_gain(-9.0) * 0.5);
+
+        // once cleared, the low-pass is out of the way and the sound as bright as before
+        playback.set_occlusion(&ctx, 0.0);
+        ctx.render(SAMPLE_RATE as usize);
+        assert_close(peak(&ctx.render(4410)), open);
+    }
+
+    #[test]
+    #[should_panic(expected = "reverb zones can only drive a Reverb effect")]
+    fn reverb_zones_need_a_reverb() {
+        let ctx = OfflineContext::new();
+        let delay = Bus::MASTER.add_effect(&ctx, Delay::new(Default::default()));
+
+        delay.set_reverb_zones(&ctx, Default::default(), vec![]);
+    }
+
+    #[test]
+    fn reverb_zones_follow_the_listener() {
+        let mut ctx = OfflineContext::new();
+        let room = Bus::new(&ctx, "room", Bus::MASTER);
+        let outside = ReverbSettings {
+            wet: 0.0,
+            ..Default::default()
+        };
+        let reverb = room.add_effect(&ctx, Reverb::new(outside));
+        let cave = ReverbZone::new(
+            ZoneShape::Sphere {
+                center: [0.0; 3],
+                radius: 1.0,
+            },
+            ReverbSettings {
+                dry: 0.25,
+                ..outside
+            },
+        );
+        reverb.set_reverb_zones(&ctx, outside, vec![cave]);
+
+        // no wet signal, so the level only follows the zone dry setting
+        sound(&ctx, &vec![0.5; 1000 * 2]).play(
+            &ctx,
+            PlaySoundParams {
+                bus: room,
+                ..looped()
+            },
+        );
+        let levels = |ctx: &mut OfflineContext, position: [f32; 3]| {
+            ctx.set_listener(Listener {
+                position,
+                ..Default::default()
+            });
+            let glide = ctx.render(SAMPLE_RATE as usize / 2);
+            let output = ctx.render(16);
+            (max_step(&glide), output[output.len() - 2])
+        };
+
+        let (_, inside) = levels(&mut ctx, [0.0; 3]);
+        assert_close(inside, 0.5 * 0.25);
+
+        // halfway through the fade around the sphere
+        let (step, fading) = levels(&mut ctx, [2.0, 0.0, 0.0]);
+        assert_close(fading, 0.5 * (0.25 + 1.0) / 2.0);
+        assert!(step < 0.01);
+
+        let (step, away) = levels(&mut ctx, [10.0, 0.0, 0.0]);
+        assert_close(away, 0.5);
+        assert!(step < 0.01);
+
+        // an empty zone list stops driving the effect, which keeps its last settings
+        reverb.set_reverb_zones(&ctx, outside, vec![]);
+        let (_, inside) = levels(&mut ctx, [0.0; 3]);
+        assert_close(inside, 0.5);
+    }
+
+    /// Load interleaved 4 channel samples through a .wav in memory.
+    fn ambisonic(ctx: &OfflineContext, samples: &[f32], format: AmbisonicFormat) -> Sound {
+        let mut wav = vec![];
+        write_wav(&mut wav, samples).unwrap();
+        // the same header, with 4 channels
+        wav[22..24].copy_from_slice(&4u16.to_le_bytes());
+        wav[28..32].copy_from_slice(&(SAMPLE_RATE * 16).to_le_bytes());
+        wav[32..34].copy_from_slice(&16u16.to_le_bytes());
+
+        Sound::load_ambisonic(ctx, &wav, format)
+    }
+
+    #[test]
+    fn ambisonic_soundfields() {
+        let mut ctx = OfflineContext::new();
+        let facing = |ctx: &mut OfflineContext, sound: &Sound, forward: [f32; 3]| {
+            ctx.set_listener(Listener {
+                forward,
+                ..Default::default()
+            });
+            let playback = sound.play(ctx, looped());
+            ctx.render(1024);
+            let output = ctx.render(16);
+            playback.stop(ctx);
+            ctx.render(1024);
+
+            (output[output.len() - 2], output[output.len() - 1])
+        };
+
+        // a sound recorded from the left, in both channel orders
+        let ambix = ambisonic(
+            &ctx,
+            &[0.5, 0.5, 0.0, 0.0].repeat(1000),
+            AmbisonicFormat::AmbiX,
+        );
+        let fuma = ambisonic(
+            &ctx,
+            &[0.5 / std::f32::consts::SQRT_2, 0.0, 0.5, 0.0].repeat(1000),
+            AmbisonicFormat::FuMa,
+        );
+        for sound in [&ambix, &fuma] {
+            let (left, right) = facing(&mut ctx, sound, [0.0, 0.0, -1.0]);
+            assert_close(left, 0.5);
+            assert_close(right, 0.0);
+
+            // the soundfield stays put in the world as the listener turns towards it, then around
+            let (left, right) = facing(&mut ctx, sound, [-1.0, 0.0, 0.0]);
+            assert_close(left, 0.25);
+            assert_close(right, 0.25);
+            let (left, right) = facing(&mut ctx, sound, [0.0, 0.0, 1.0]);
+            assert_close(left, 0.0);
+            assert_close(right, 0.5);
+        }
+
+        // with several listeners, the first one turns the soundfield
+        let turned = Listener {
+            forward: [0.0, 0.0, 1.0],
+            ..Default::default()
+        };
+        ctx.set_listeners(&[turned, Listener::default()], ListenerMix::Blend);
+        let playback = ambix.play(&ctx, looped());
+        ctx.render(1024);
+        let output = ctx.render(16);
+        playback.stop(&ctx);
+        assert_close(output[output.len() - 2], 0.0);
+        assert_close(output[output.len() - 1], 0.5);
+
+        // binaural decoding, the head shadows the high frequencies from the far ear
+        ctx.set_ambisonic_panning(Panning::Hrtf);
+        ctx.set_listener(Listener::default());
+        let left_side = sine(4000.0, 4410)
+            .chunks_exact(2)
+            .flat_map(|frame| [frame[0], frame[0], 0.0, 0.0])
+            .collect::<Vec<_>>();
+        let playback = ambisonic(&ctx, &left_side, AmbisonicFormat::AmbiX).play(&ctx, looped());
+        ctx.render(1024);
+        let output = ctx.render(1024);
+        playback.stop(&ctx);
+        let energy =
+            |ear: usize| -> f32 { output.iter().skip(ear).step_by(2).map(|s| s * s).sum() };
+        assert!(energy(0) > energy(1) * 2.0);
+    }
+
+    #[test]
+    fn multiple_listeners() {
+        let mut ctx = OfflineContext::new();
+        let listeners = [
+            Listener::default(),
+            Listener {
+                position: [10.0, 0.0, 0.0],
+                ..Default::default()
+            },
+        ];
+        // right next to the second listener, on its left, and 9 units right of the first one
+        let emitter = Emitter {
+            position: [9.0, 0.0, 0.0],
+            ..Default::default()
+        };
+
+        ctx.set_listeners(&listeners, ListenerMix::Nearest);
+        let (left, right) = spatialized(&mut ctx, emitter);
+        assert_close(left, 0.5 * std::f32::consts::SQRT_2);
+        assert_close(right, 0.0);
+
+        // weighted by inverse distance: 0.9 for the second listener, 0.1 for the first one
+        ctx.set_listeners(&listeners, ListenerMix::Blend);
+        let gain = 0.9 * 1.0 + 0.1 / 9.0;
+        let angle = (-0.9 + 0.1 + 1.0) * std::f32::consts::FRAC_PI_4;
+        let (left, right) = spatialized(&mut ctx, emitter);
+        assert_close(left, 0.5 * gain * std::f32::consts::SQRT_2 * angle.cos());
+        assert_close(right, 0.5 * gain * std::f32::consts::SQRT_2 * angle.sin());
+
+        // a single listener again
+        ctx.set_listener(Listener::default());
+        let (left, right) = spatialized(&mut ctx, emitter);
+        assert_close(left, 0.0);
+        assert_close(right, 0.5 / 9.0 * std::f32::consts::SQRT_2);
+    }
+}
@@ -0,0 +1,90 @@
+//! Volume ramps, for fades and declicking.
+
+use crate::FadeCurve;
+
+/// Instant volume changes and pauses are smoothed over this many frames to avoid clicks.
+pub const DECLICK_FRAMES: usize = 256;
+
+/// Gain, moving from `from` to `to` over `frames` frames.
+#[derive(Debug)]
+pub struct Ramp {
+    from: f32,
+    to: f32,
+    curve: FadeCurve,
+    frame: usize,
+    frames: usize,
+}
+
+impl Ramp {
+    pub fn new(value: f32) -> Ramp {
+        Ramp {
+            from: value,
+            to: value,
+            curve: FadeCurve::Linear,
+            frame: 0,
+            frames: 0,
+        }
+    }
+
+    /// Start moving to `to` from wherever the ramp is right now.
+    pub fn start(&mut self, to: f32, frames: usize, curve: FadeCurve) {
+        self.from = self.value();
+        self.to = to;
+        self.curve = curve;
+        self.frame = 0;
+        self.frames = frames;
+    }
+
+    fn value(&self) -> f32 {
+        if self.is_finished() {
+            return self.to;
+        }
+
+        fade_value(
+            self.curve,
+            self.from,
+            self.to,
+            self.frame as f32 / self.frames as f32,
+        )
+    }
+
+    pub fn next(&mut self) -> f32 {
+        let value = self.value();
+
+        if !self.is_finished() {
+            self.frame += 1;
+        }
+
+        value
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.frame >= self.frames
+    }
+}
+
+/// Value of a `curve` ramp from `from` to `to` at `t`, 0.0 to 1.0.
+fn fade_value(curve: FadeCurve, from: f32, to: f32, t: f32) -> f32 {
+    match curve {
+        FadeCurve::Linear => from + (to - from) * t,
+        FadeCurve::SCurve => from + (to - from) * t * t * (3.0 - 2.0 * t),
+        FadeCurve::EqualPower => {
+            let angle = t * std::f32::consts::FRAC_PI_2;
+
+            if to >= from {
+                from + (to - from) * angle.sin()
+            } else {
+                from + (to - from) * (1.0 - angle.cos())
+            }
+        }
+        FadeCurve::Exponential => {
+            // -60dB, silence can't be reached exponentially
+            const FLOOR: f32 = 0.001;
+
+            let from = from.max(FLOOR).ln();
+            let to = to.max(FLOOR).ln();
+
+            (from + (to - from) * t).exp()
+        }
+    }
+}
@@ -0,0 +1,421 @@
+//! Positional audio: a listener and emitters in 3D space.
+//!
+//! Coordinates are right-handed, in any units as long as distances use the same ones.
+//! By default the listener stands at the origin, looking down -Z with +Y up.
+
+use crate::{
+    effects::{AudioEffect, BiquadFilter},
+    limiter::db_to_gain,
+};
+
+/// Where the sound is heard from, set with `AudioContext::set_listener`
+/// or `AudioContext::set_listeners`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Listener {
+    pub position: [f32; 3],
+    /// Direction the listener is facing.
+    pub forward: [f32; 3],
+    /// Top of the listener's head, perpendicular to `forward`.
+    pub up: [f32; 3],
+    /// In distance units per second, for the Doppler effect.
+    pub velocity: [f32; 3],
+}
+
+impl Default for Listener {
+    fn default() -> Listener {
+        Listener {
+            position: [0.0; 3],
+            forward: [0.0, 0.0, -1.0],
+            up: [0.0, 1.0, 0.0],
+            velocity: [0.0; 3],
+        }
+    }
+}
+
+/// How emitters are mixed when there are several listeners, for split-screen games.
+/// Ambisonic sounds are always heard from the first listener.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ListenerMix {
+    /// Each emitter is heard from the listener closest to it.
+    Nearest,
+    /// Each emitter is heard from all the listeners, weighted by the inverse of their distance,
+    /// smoothing out the jumps when the nearest listener changes.
+    Blend,
+}
+
+/// How volume falls off with the distance between the emitter and the listener.
+///
+/// Distance is clamped between `min_distance` and `max_distance` first,
+/// so closer emitters play at full volume and farther ones stop getting quieter.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DistanceModel {
+    /// `min / (min + rolloff * (distance - min))`, physically plausible.
+    Inverse,
+    /// `1 - rolloff * (distance - min) / (max - min)`, silent at `max_distance` with rolloff 1.0.
+    Linear,
+    /// `(distance / min) ^ -rolloff`.
+    Exponential,
+}
+
+/// How the direction to an emitter is rendered.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Panning {
+    /// Left/right pan with the playback `PanLaw`. Works with any speakers.
+    Stereo,
+    /// Binaural rendering with the context `HrtfSet`, with elevation and front/back cues.
+    /// Meant for headphones. The playback is mixed down to mono first.
+    Hrtf,
+}
+
+/// Directivity of an emitter, like a loudspeaker or a person talking.
+///
+/// Listeners inside the inner cone hear the emitter normally, listeners outside
+/// the outer cone hear it at `outer_gain` and low-passed at `outer_cutoff`,
+/// with a smooth transition in between. Angles are the full cone widths, in degrees;
+/// 360.0 makes the emitter omnidirectional.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Cone {
+    pub inner_angle: f32,
+    pub outer_angle: f32,
+    pub outer_gain: f32,
+    /// Low-pass cutoff outside of the outer cone, in Hz.
+    pub outer_cutoff: f32,
+}
+
+impl Default for Cone {
+    fn default() -> Cone {
+        Cone {
+            inner_angle: 360.0,
+            outer_angle: 360.0,
+            outer_gain: 0.0,
+            outer_cutoff: OPEN_CUTOFF,
+        }
+    }
+}
+
+/// Low-pass cutoffs at or above this frequency, in Hz, mean no filtering at all.
+pub(crate) const OPEN_CUTOFF: f32 = 20000.0;
+
+/// Gain at full occlusion or obstruction, in dB.
+const OCCLUDED_GAIN: f32 = -18.0;
+/// Low-pass cutoff at full occlusion or obstruction, in Hz.
+const OCCLUDED_CUTOFF: f32 = 500.0;
+/// Occlusion and obstruction changes are smoothed over roughly this many seconds,
+/// so raycasts flickering between hit and miss do not sound choppy.
+pub(crate) const OCCLUSION_TIME: f32 = 0.1;
+
+/// Position of a playback in 3D space, set with `PlaySoundParams::emitter`
+/// and updated with `Playback::set_emitter` or `Playback::set_position`.
+///
+/// Spatialized playbacks get their volume from the distance model
+/// and their pan from the direction to the listener, replacing `PlaySoundParams::pan`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Emitter {
+    pub position: [f32; 3],
+    /// In distance units per second, for the Doppler effect.
+    pub velocity: [f32; 3],
+    pub distance_model: DistanceModel,
+    /// Distance at which attenuation starts.
+    pub min_distance: f32,
+    /// Distance after which attenuation stops.
+    pub max_distance: f32,
+    /// How fast the volume falls off, 1.0 is the natural rate for `DistanceModel::Inverse`.
+    pub rolloff: f32,
+    pub panning: Panning,
+    /// Direction the emitter is facing, only matters with a `cone`.
+    pub orientation: [f32; 3],
+    pub cone: Cone,
+}
+
+impl Default for Emitter {
+    fn default() -> Emitter {
+        Emitter {
+            position: [0.0; 3],
+            velocity: [0.0; 3],
+            distance_model: DistanceModel::Inverse,
+            min_distance: 1.0,
+            max_distance: 100.0,
+            rolloff: 1.0,
+            panning: Panning::Stereo,
+            orientation: [0.0, 0.0, -1.0],
+            cone: Cone::default(),
+        }
+    }
+}
+
+impl Emitter {
+    /// Volume multiplier at `distance` from the listener.
+    pub fn attenuation(&self, distance: f32) -> f32 {
+        let min = self.min_distance.max(0.0001);
+        let max = self.max_distance.max(min);
+        let distance = distance.clamp(min, max);
+
+        let gain = match self.distance_model {
+            DistanceModel::Inverse => min / (min + self.rolloff * (distance - min)),
+            DistanceModel::Linear if max > min => {
+                1.0 - self.rolloff * (distance - min) / (max - min)
+            }
+            DistanceModel::Linear => 1.0,
+            DistanceModel::Exponential => (distance / min).powf(-self.rolloff),
+        };
+
+        gain.clamp(0.0, 1.0)
+    }
+}
+
+/// Emitter position relative to the listener, in the listener's own axes:
+/// x to the right, y up, z backwards.
+pub(crate) fn to_listener_space(listener: &Listener, position: [f32; 3]) -> [f32; 3] {
+    to_listener_axes(listener, sub(position, listener.position))
+}
+
+/// A direction in the listener's own axes, ignoring the listener position.
+pub(crate) fn to_listener_axes(listener: &Listener, direction: [f32; 3]) -> [f32; 3] {
+    let forward = normalize(listener.forward);
+    let right = normalize(cross(forward, listener.up));
+    let up = cross(right, forward);
+
+    [
+        dot(direction, right),
+        dot(direction, up),
+        -dot(direction, forward),
+    ]
+}
+
+/// How an emitter is heard by the listeners.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) struct Spatialized {
+    pub gain: f32,
+    pub pan: f32,
+    /// Low-pass cutoff, in Hz, `OPEN_CUTOFF` for none.
+    pub cutoff: f32,
+    /// Unit vector towards the emitter in listener space, for the HRTF.
+    pub direction: [f32; 3],
+    /// Doppler pitch multiplier.
+    pub doppler: f32,
+}
+
+/// Distance attenuation, directivity, direction and Doppler shift of an emitter.
+pub(crate) fn spatialize(
+    listeners: &[Listener],
+    mix: ListenerMix,
+    emitter: &Emitter,
+    speed_of_sound: f32,
+    doppler_factor: f32,
+) -> Spatialized {
+    let hear = |listener: &Listener| {
+        let relative = to_listener_space(listener, emitter.position);
+        let distance = length(relative);
+        let pan = if distance > 0.0 {
+            relative[0] / distance
+        } else {
+            0.0
+        };
+        let (cone_gain, cutoff) = cone(listener, emitter);
+
+        Spatialized {
+            gain: emitter.attenuation(distance) * cone_gain,
+            pan,
+            cutoff,
+            direction: normalize(relative),
+            doppler: doppler(listener, emitter, speed_of_sound, doppler_factor),
+        }
+    };
+    // distances below `min_distance` all count the same, and never divide by zero
+    let distance = |listener: &Listener| {
+        length(sub(emitter.position, listener.position)).max(emitter.min_distance.max(0.0001))
+    };
+
+    let nearest = listeners
+        .iter()
+        .min_by(|a, b| distance(a).total_cmp(&distance(b)))
+        .map_or_else(|| hear(&Listener::default()), hear);
+    if mix == ListenerMix::Nearest || listeners.len() < 2 {
+        return nearest;
+    }
+
+    let total: f32 = listeners.iter().map(|l| 1.0 / distance(l)).sum();
+    let mut blend = Spatialized {
+        gain: 0.0,
+        pan: 0.0,
+        cutoff: 0.0,
+        direction: [0.0; 3],
+        doppler: 0.0,
+    };
+    for listener in listeners {
+        let weight = 1.0 / distance(listener) / total;
+        let heard = hear(listener);
+
+        blend.gain += heard.gain * weight;
+        blend.pan += heard.pan * weight;
+        // cutoff is averaged in octaves
+        blend.cutoff += heard.cutoff.ln() * weight;
+        blend.doppler += heard.doppler * weight;
+        for (blended, direction) in blend.direction.iter_mut().zip(heard.direction) {
+            *blended += direction * weight;
+        }
+    }
+    blend.cutoff = blend.cutoff.exp();
+    // opposite directions cancel out, then the nearest listener decides
+    blend.direction = if length(blend.direction) > 1e-3 {
+        normalize(blend.direction)
+    } else {
+        nearest.direction
+    };
+
+    blend
+}
+
+/// Gain and low-pass cutoff from the emitter cone.
+fn cone(listener: &Listener, emitter: &Emitter) -> (f32, f32) {
+    let cone = &emitter.cone;
+    let to_listener = sub(listener.position, emitter.position);
+    let distance = length(to_listener);
+    let orientation = normalize(emitter.orientation);
+
+    if cone.inner_angle >= 360.0 || distance == 0.0 || length(orientation) == 0.0 {
+        return (1.0, OPEN_CUTOFF);
+    }
+
+    let angle = (dot(to_listener, orientation) / distance)
+        .clamp(-1.0, 1.0)
+        .acos()
+        .to_degrees();
+    let inner = cone.inner_angle.max(0.0) / 2.0;
+    let outer = (cone.outer_angle / 2.0).max(inner);
+
+    // 0.0 inside the inner cone, 1.0 outside of the outer one
+    let t = if angle <= inner {
+        0.0
+    } else if angle >= outer {
+        1.0
+    } else {
+        (angle - inner) / (outer - inner)
+    };
+
+    let gain = 1.0 + (cone.outer_gain - 1.0) * t;
+    // cutoff is interpolated on a log scale, linear in octaves
+    let outer_cutoff = cone.outer_cutoff.clamp(10.0, OPEN_CUTOFF);
+    let cutoff = OPEN_CUTOFF * (outer_cutoff / OPEN_CUTOFF).powf(t);
+
+    (gain, cutoff)
+}
+
+/// Gain and low-pass cutoff for an occlusion or obstruction amount from 0.0 to 1.0.
+/// Both fall off linearly in dB and octaves, which sounds even across the range.
+pub(crate) fn occlusion(amount: f32) -> (f32, f32) {
+    let amount = amount.clamp(0.0, 1.0);
+
+    (
+        db_to_gain(OCCLUDED_GAIN * amount),
+        OPEN_CUTOFF * (OCCLUDED_CUTOFF / OPEN_CUTOFF).powf(amount),
+    )
+}
+
+/// Low-pass of a playback cone, occlusion or obstruction, only in the signal path while
+/// its cutoff is below `OPEN_CUTOFF`. Even there, a low-pass is not transparent.
+///
+/// The filter is crossfaded with the unfiltered signal over a buffer when it comes in,
+/// to hide its start from silence, and when it goes out, instead of sweeping its cutoff
+/// all the way up: large quick cutoff changes make a biquad ring.
+#[derive(Debug, Default)]
+pub(crate) struct PathFilter {
+    filter: Option<BiquadFilter>,
+}
+
+impl PathFilter {
+    /// Filter interleaved stereo `buffer` in place, `dry` is scratch space for the crossfades.
+    pub fn process(&mut self, cutoff: f32, buffer: &mut [f32], dry: &mut Vec<f32>) {
+        let engaging = self.filter.is_none();
+        let disengaging = cutoff >= OPEN_CUTOFF;
+        if engaging && disengaging {
+            return;
+        }
+
+        let filter = self
+            .filter
+            .get_or_insert_with(|| BiquadFilter::low_pass(cutoff));
+        if !disengaging {
+            filter.set_parameter(BiquadFilter::CUTOFF, cutoff);
+        }
+
+        if !engaging && !disengaging {
+            filter.process(buffer);
+            return;
+        }
+
+        dry.clear();
+        dry.extend_from_slice(buffer);
+        filter.process(buffer);
+
+        let frames = buffer.len() / 2;
+        for (n, (wet, dry)) in buffer
+            .chunks_exact_mut(2)
+            .zip(dry.chunks_exact(2))
+            .enumerate()
+        {
+            let t = n as f32 / frames as f32;
+            let mix = if engaging { t } else { 1.0 - t };
+
+            wet[0] = dry[0] + (wet[0] - dry[0]) * mix;
+            wet[1] = dry[1] + (wet[1] - dry[1]) * mix;
+        }
+
+        if disengaging {
+            self.filter = None;
+        }
+    }
+}
+
+/// Doppler pitch multiplier, the OpenAL 1.1 formula.
+fn doppler(
+    listener: &Listener,
+    emitter: &Emitter,
+    speed_of_sound: f32,
+    doppler_factor: f32,
+) -> f32 {
+    let direction = sub(listener.position, emitter.position);
+    let distance = length(direction);
+
+    if distance == 0.0 || doppler_factor <= 0.0 || speed_of_sound <= 0.0 {
+        return 1.0;
+    }
+
+    // velocities along the emitter to listener line, capped below the speed of sound
+    let limit = speed_of_sound / doppler_factor * 0.99;
+    let listener_speed = (dot(direction, listener.velocity) / distance).min(limit);
+    let emitter_speed = (dot(direction, emitter.velocity) / distance).min(limit);
+
+    (speed_of_sound - doppler_factor * listener_speed)
+        / (speed_of_sound - doppler_factor * emitter_speed)
+}
+
+pub(crate) fn sub(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+    [a[0] - b[0], a[1] - b[1], a[2] - b[2]]
+}
+
+pub(crate) fn dot(a: [f32; 3], b: [f32; 3]) -> f32 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+pub(crate) fn cross(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+    [
+        a[1] * b[2] - a[2] * b[1],
+        a[2] * b[0] - a[0] * b[2],
+        a[0] * b[1] - a[1] * b[0],
+    ]
+}
+
+pub(crate) fn length(a: [f32; 3]) -> f32 {
+    dot(a, a).sqrt()
+}
+
+pub(crate) fn normalize(a: [f32; 3]) -> [f32; 3] {
+    let length = length(a);
+
+    if length > 0.0 {
+        [a[0] / length, a[1] / length, a[2] / length]
+    } else {
+        a
+    }
+}
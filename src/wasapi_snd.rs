@@ -1,9 +1,9 @@
 // https://github.com/floooh/sokol/blob/master/sokol_audio.h
 // https://github.com/norse-rs/audir/blob/master/audir/src/wasapi/mod.rs
 
-use crate::PlaySoundParams;
+use crate::{AmbisonicFormat, HrtfSet, Limiter, Listener, ListenerMix, Panning, PlaySoundParams};
 
-pub use crate::mixer::Playback;
+pub use crate::mixer::{Bus, Playback};
 
 use winapi::shared::guiddef::{CLSID, IID};
 use winapi::shared::ksmedia;
@@ -201,6 +201,44 @@ impl AudioContext {
 
         AudioContext { mixer_ctrl }
     }
+
+    /// Enable or disable the master output limiter. Disabled by default.
+    pub fn set_limiter(&self, limiter: Option<Limiter>) {
+        self.mixer_ctrl.set_limiter(limiter);
+    }
+
+    /// Move the listener of the spatialized playbacks.
+    pub fn set_listener(&self, listener: Listener) {
+        self.mixer_ctrl
+            .set_listeners(&[listener], ListenerMix::Nearest);
+    }
+
+    /// Several listeners, for split-screen games, `mix` decides how each emitter is heard by them.
+    pub fn set_listeners(&self, listeners: &[Listener], mix: ListenerMix) {
+        self.mixer_ctrl.set_listeners(listeners, mix);
+    }
+
+    /// How ambisonic sounds are decoded: `Panning::Stereo` by default,
+    /// `Panning::Hrtf` for headphones, with the context `HrtfSet`.
+    pub fn set_ambisonic_panning(&self, panning: Panning) {
+        self.mixer_ctrl.set_ambisonic_panning(panning);
+    }
+
+    /// Doppler pitch shift of moving emitters.
+    /// `speed_of_sound` is in distance units per second, 343.3 by default for meters,
+    /// `doppler_factor` exaggerates (above 1.0) or reduces (below 1.0) the effect, 0.0 disables it.
+    pub fn set_doppler(&self, speed_of_sound: f32, doppler_factor: f32) {
+        self.mixer_ctrl.set_doppler(speed_of_sound, doppler_factor);
+    }
+
+    /// Replace the head related impulse responses used by `Panning::Hrtf` emitters.
+    pub fn set_hrtf(&self, hrtf: HrtfSet) {
+        self.mixer_ctrl.set_hrtf(hrtf);
+    }
+
+    pub(crate) fn from_mixer_ctrl(mixer_ctrl: crate::mixer::MixerControl) -> AudioContext {
+        AudioContext { mixer_ctrl }
+    }
 }
 
 pub struct Sound {
@@ -214,18 +252,55 @@ impl Sound {
         Sound { sound_id }
     }
 
+    /// Load a 4 channel first order ambisonic recording, for ambiences.
+    /// The soundfield stays put in the world and turns as the listener looks around,
+    /// its front is the default listener forward direction.
+    /// A soundfield has no position to pick or weight listeners by, so with several
+    /// listeners it always turns with the first one, whatever the `ListenerMix`.
+    pub fn load_ambisonic(ctx: &AudioContext, data: &[u8], format: AmbisonicFormat) -> Sound {
+        let sound_id = ctx.mixer_ctrl.load_ambisonic(data, format);
+
+        Sound { sound_id }
+    }
+
     pub fn play(&self, ctx: &AudioContext, params: PlaySoundParams) -> Playback {
         ctx.mixer_ctrl.play(self.sound_id, params)
     }
 
+    /// Start playing this sound while fading `from` out over `duration` seconds.
+    pub fn crossfade(
+        &self,
+        ctx: &AudioContext,
+        from: Playback,
+        params: PlaySoundParams,
+        duration: f32,
+    ) -> Playback {
+        ctx.mixer_ctrl
+            .crossfade(from, self.sound_id, params, duration)
+    }
+
     pub fn stop(&self, ctx: &AudioContext) {
         ctx.mixer_ctrl.stop_all(self.sound_id);
     }
 
+    /// Pause all the playbacks of this sound.
+    pub fn pause_all(&self, ctx: &AudioContext) {
+        ctx.mixer_ctrl.pause_all(self.sound_id);
+    }
+
+    pub fn resume_all(&self, ctx: &AudioContext) {
+        ctx.mixer_ctrl.resume_all(self.sound_id);
+    }
+
     pub fn set_volume(&self, ctx: &AudioContext, volume: f32) {
         ctx.mixer_ctrl.set_volume_all(self.sound_id, volume);
     }
 
+    /// Length of the sound, in seconds.
+    pub fn duration(&self, ctx: &AudioContext) -> f32 {
+        ctx.mixer_ctrl.duration(self.sound_id)
+    }
+
     pub fn delete(&self, ctx: &AudioContext) {
         ctx.mixer_ctrl.delete(self.sound_id);
     }
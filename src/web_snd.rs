@@ -1,15 +1,60 @@
-use crate::PlaySoundParams;
+//! WebAudio backend. Decoding, mixing, buses and the limiter are done by the browser.
+//!
+//! Spatialization, ambisonic decoding, effects and ducking need the native mixer:
+//! their functions exist here with the same signatures, so the same code builds
+//! for every platform, but they are ignored.
+
+use crate::{
+    effects::{AudioEffect, ReverbSettings},
+    AmbisonicFormat, Ducking, Emitter, FadeCurve, HrtfSet, Limiter, Listener, ListenerMix, PanLaw,
+    Panning, PlaySoundParams, ReverbZone,
+};
+
+use std::any::Any;
 
 extern "C" {
     fn audio_init();
+    fn audio_set_limiter(enabled: bool, ceiling: f32, release: f32, soft_clip: bool);
+    fn audio_bus_new(name: *const u8, name_len: u32, parent: u32) -> u32;
+    fn audio_bus_find(name: *const u8, name_len: u32) -> i32;
+    fn audio_bus_set_volume(bus: u32, volume: f32);
+    fn audio_bus_set_muted(bus: u32, muted: bool);
+    fn audio_bus_set_solo(bus: u32, solo: bool);
     fn audio_add_buffer(content: *const u8, content_len: u32) -> u32;
-    fn audio_play_buffer(buffer: u32, volume: f32, repeat: bool) -> u32;
+    fn audio_add_ambisonic_buffer(content: *const u8, content_len: u32, w_gain: f32) -> u32;
+    fn audio_play_buffer(
+        buffer: u32,
+        volume: f32,
+        repeat: bool,
+        offset: f32,
+        pitch: f32,
+        pan: f32,
+        pan_linear: bool,
+        fade_in: f32,
+        fade_in_curve: u32,
+        bus: u32,
+    ) -> u32;
     fn audio_source_is_loaded(buffer: u32) -> bool;
     fn audio_source_set_volume(buffer: u32, volume: f32);
     fn audio_source_stop(buffer: u32);
+    fn audio_source_pause(buffer: u32);
+    fn audio_source_resume(buffer: u32);
     fn audio_source_delete(buffer: u32);
+    fn audio_source_duration(buffer: u32) -> f32;
     fn audio_playback_stop(playback: u32);
     fn audio_playback_set_volume(playback: u32, volume: f32);
+    fn audio_playback_fade_to(playback: u32, volume: f32, duration: f32, curve: u32);
+    fn audio_playback_stop_with_fade(playback: u32, duration: f32, curve: u32);
+    fn audio_playback_set_pitch(playback: u32, pitch: f32);
+    fn audio_playback_set_pan(playback: u32, pan: f32);
+    fn audio_playback_add_send(playback: u32, bus: u32, level: f32);
+    fn audio_playback_seek(playback: u32, position: f32);
+    fn audio_playback_pause(playback: u32);
+    fn audio_playback_resume(playback: u32);
+    fn audio_playback_is_paused(playback: u32) -> bool;
+    fn audio_playback_is_playing(playback: u32) -> bool;
+    fn audio_playback_position(playback: u32) -> f32;
+    fn audio_playback_remaining(playback: u32) -> f32;
 }
 
 #[no_mangle]
@@ -27,10 +72,122 @@ impl AudioContext {
 
         AudioContext
     }
+
+    /// Enable or disable the master output limiter. Disabled by default.
+    ///
+    /// Only an approximation on web: a 20:1 `DynamicsCompressorNode` with its own fixed lookahead,
+    /// which lets short peaks through over the ceiling. `Limiter::lookahead` is ignored.
+    pub fn set_limiter(&self, limiter: Option<Limiter>) {
+        let enabled = limiter.is_some();
+        let limiter = limiter.unwrap_or_default();
+
+        unsafe { audio_set_limiter(enabled, limiter.ceiling, limiter.release, limiter.soft_clip) }
+    }
+
+    /// Move the listener of the spatialized playbacks. Ignored on web.
+    pub fn set_listener(&self, _listener: Listener) {}
+
+    /// Several listeners, for split-screen games. Ignored on web.
+    pub fn set_listeners(&self, _listeners: &[Listener], _mix: ListenerMix) {}
+
+    /// How ambisonic sounds are decoded. Ignored on web.
+    pub fn set_ambisonic_panning(&self, _panning: Panning) {}
+
+    /// Doppler pitch shift of moving emitters. Ignored on web.
+    pub fn set_doppler(&self, _speed_of_sound: f32, _doppler_factor: f32) {}
+
+    /// Replace the head related impulse responses. Ignored on web.
+    pub fn set_hrtf(&self, _hrtf: HrtfSet) {}
 }
 
 pub struct Sound(u32);
 
+/// A group of playbacks with its own volume, mute and solo.
+///
+/// Buses nest: playbacks are mixed into their bus, the bus output goes
+/// to its parent bus, and eventually everything ends up in `Bus::MASTER`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Bus {
+    pub(crate) id: u32,
+}
+
+impl Bus {
+    /// The bus directly connected to the output.
+    pub const MASTER: Bus = Bus { id: 0 };
+
+    pub fn new(_ctx: &AudioContext, name: &str, parent: Bus) -> Bus {
+        let id = unsafe { audio_bus_new(name.as_ptr(), name.len() as u32, parent.id) };
+
+        Bus { id }
+    }
+
+    /// Bus created with a given name, "Master" for `Bus::MASTER`.
+    pub fn find(_ctx: &AudioContext, name: &str) -> Option<Bus> {
+        let bus = unsafe { audio_bus_find(name.as_ptr(), name.len() as u32) };
+
+        if bus < 0 {
+            None
+        } else {
+            Some(Bus { id: bus as u32 })
+        }
+    }
+
+    pub fn set_volume(&self, _ctx: &AudioContext, volume: f32) {
+        unsafe { audio_bus_set_volume(self.id, volume) }
+    }
+
+    pub fn set_muted(&self, _ctx: &AudioContext, muted: bool) {
+        unsafe { audio_bus_set_muted(self.id, muted) }
+    }
+
+    /// While any bus is soloed, only playbacks in soloed buses and their child buses are heard.
+    pub fn set_solo(&self, _ctx: &AudioContext, solo: bool) {
+        unsafe { audio_bus_set_solo(self.id, solo) }
+    }
+
+    /// Automatically lower this bus volume while another bus is playing. Ignored on web.
+    pub fn set_ducking(&self, _ctx: &AudioContext, _ducking: Option<Ducking>) {}
+
+    /// Append an effect to the bus effect chain.
+    /// Effects are not run on web, the returned handle does nothing.
+    pub fn add_effect(&self, _ctx: &AudioContext, _effect: impl AudioEffect + 'static) -> Effect {
+        Effect
+    }
+}
+
+/// An `AudioEffect` inserted into a bus or a playback.
+/// Effects are not run on web, all the functions are ignored.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Effect;
+
+impl Effect {
+    /// Delay the effect introduces, in seconds. Always 0.0 on web.
+    pub fn latency_seconds(&self) -> f32 {
+        0.0
+    }
+
+    pub fn set_parameter(&self, _ctx: &AudioContext, _index: u32, _value: f32) {}
+
+    pub fn set_data(&self, _ctx: &AudioContext, _data: impl Any + Send) {}
+
+    pub fn set_mix(&self, _ctx: &AudioContext, _mix: f32) {}
+
+    pub fn set_bypass(&self, _ctx: &AudioContext, _bypass: bool) {}
+
+    pub fn reset(&self, _ctx: &AudioContext) {}
+
+    pub fn remove(self, _ctx: &AudioContext) {}
+
+    /// Drive a reverb from the listener position. Ignored on web.
+    pub fn set_reverb_zones(
+        &self,
+        _ctx: &AudioContext,
+        _outside: ReverbSettings,
+        _zones: Vec<ReverbZone>,
+    ) {
+    }
+}
+
 pub struct Playback(u32);
 
 impl Playback {
@@ -38,9 +195,86 @@ impl Playback {
         unsafe { audio_playback_stop(self.0) }
     }
 
+    /// Fade out over `duration` seconds, then stop.
+    pub fn stop_with_fade(self, _ctx: &AudioContext, duration: f32, curve: FadeCurve) {
+        unsafe { audio_playback_stop_with_fade(self.0, duration, curve as u32) }
+    }
+
     pub fn set_volume(&self, _ctx: &AudioContext, volume: f32) {
         unsafe { audio_playback_set_volume(self.0, volume) }
     }
+
+    /// Ramp volume from its current value to `volume` over `duration` seconds.
+    pub fn fade_to(&self, _ctx: &AudioContext, volume: f32, duration: f32, curve: FadeCurve) {
+        unsafe { audio_playback_fade_to(self.0, volume, duration, curve as u32) }
+    }
+
+    /// Playback speed. 1.0 is the original pitch, 2.0 is twice as fast and an octave higher.
+    pub fn set_pitch(&self, _ctx: &AudioContext, pitch: f32) {
+        unsafe { audio_playback_set_pitch(self.0, pitch) }
+    }
+
+    /// Stereo position, from -1.0 (left) through 0.0 (center) to 1.0 (right).
+    pub fn set_pan(&self, _ctx: &AudioContext, pan: f32) {
+        unsafe { audio_playback_set_pan(self.0, pan) }
+    }
+
+    /// Make the playback positional. Ignored on web.
+    pub fn set_emitter(&self, _ctx: &AudioContext, _emitter: Option<Emitter>) {}
+
+    /// Move the emitter of a positional playback. Ignored on web.
+    pub fn set_position(&self, _ctx: &AudioContext, _position: [f32; 3]) {}
+
+    /// Change the velocity of a positional playback emitter. Ignored on web.
+    pub fn set_velocity(&self, _ctx: &AudioContext, _velocity: [f32; 3]) {}
+
+    /// Muffle the playback as if heard through a wall. Ignored on web.
+    pub fn set_occlusion(&self, _ctx: &AudioContext, _occlusion: f32) {}
+
+    /// Muffle the direct sound of the playback. Ignored on web.
+    pub fn set_obstruction(&self, _ctx: &AudioContext, _obstruction: f32) {}
+
+    /// Append an effect to the playback effect chain.
+    /// Effects are not run on web, the returned handle does nothing.
+    pub fn add_effect(&self, _ctx: &AudioContext, _effect: impl AudioEffect + 'static) -> Effect {
+        Effect
+    }
+
+    /// Move playback to a given position in the sound, in seconds.
+    pub fn seek(&self, _ctx: &AudioContext, position: f32) {
+        unsafe { audio_playback_seek(self.0, position) }
+    }
+
+    /// Stop mixing the playback, keeping its position, until `resume` is called.
+    pub fn pause(&self, _ctx: &AudioContext) {
+        unsafe { audio_playback_pause(self.0) }
+    }
+
+    pub fn resume(&self, _ctx: &AudioContext) {
+        unsafe { audio_playback_resume(self.0) }
+    }
+
+    /// `false` once the playback reached the end of a non-looped sound or was stopped.
+    /// Paused playback is still playing.
+    pub fn is_playing(&self) -> bool {
+        unsafe { audio_playback_is_playing(self.0) }
+    }
+
+    pub fn is_paused(&self) -> bool {
+        unsafe { audio_playback_is_paused(self.0) }
+    }
+
+    /// Current position in the sound, in seconds.
+    /// Wraps back to 0 on each iteration of a looped playback.
+    pub fn position(&self) -> f32 {
+        unsafe { audio_playback_position(self.0) }
+    }
+
+    /// Time left until the end of the sound, in seconds, at the current pitch.
+    /// For looped playbacks - until the end of the current iteration.
+    pub fn remaining(&self) -> f32 {
+        unsafe { audio_playback_remaining(self.0) }
+    }
 }
 
 impl Sound {
@@ -49,6 +283,19 @@ impl Sound {
         Sound(buffer)
     }
 
+    /// Load a 4 channel first order ambisonic recording.
+    /// Soundfields are not decoded on web, only the omnidirectional W channel is played.
+    pub fn load_ambisonic(_ctx: &AudioContext, data: &[u8], format: AmbisonicFormat) -> Sound {
+        let w_gain = match format {
+            AmbisonicFormat::AmbiX => 1.0,
+            AmbisonicFormat::FuMa => std::f32::consts::SQRT_2,
+        };
+        let buffer =
+            unsafe { audio_add_ambisonic_buffer(data.as_ptr(), data.len() as u32, w_gain) };
+
+        Sound(buffer)
+    }
+
     /// WASM requirement - sound may be used only after it is is_loaded
     /// something like will do:
     ///```skip
@@ -63,19 +310,71 @@ impl Sound {
     }
 
     pub fn play(&self, _ctx: &AudioContext, params: PlaySoundParams) -> Playback {
-        let id = unsafe { audio_play_buffer(self.0, params.volume, params.looped) };
+        let id = unsafe {
+            audio_play_buffer(
+                self.0,
+                params.volume,
+                params.looped,
+                params.start_offset,
+                params.pitch,
+                params.pan,
+                params.pan_law == PanLaw::Linear,
+                params.fade_in,
+                params.fade_in_curve as u32,
+                params.bus.id,
+            )
+        };
+
+        for (bus, level) in params.sends {
+            unsafe { audio_playback_add_send(id, bus.id, level) };
+        }
 
         Playback(id)
     }
 
+    /// Start playing this sound while fading `from` out over `duration` seconds.
+    pub fn crossfade(
+        &self,
+        ctx: &AudioContext,
+        from: Playback,
+        params: PlaySoundParams,
+        duration: f32,
+    ) -> Playback {
+        from.stop_with_fade(ctx, duration, FadeCurve::EqualPower);
+
+        self.play(
+            ctx,
+            PlaySoundParams {
+                fade_in: duration,
+                fade_in_curve: FadeCurve::EqualPower,
+                ..params
+            },
+        )
+    }
+
     pub fn stop(&self, _ctx: &AudioContext) {
         unsafe { audio_source_stop(self.0) }
     }
 
+    /// Pause all the playbacks of this sound.
+    pub fn pause_all(&self, _ctx: &AudioContext) {
+        unsafe { audio_source_pause(self.0) }
+    }
+
+    pub fn resume_all(&self, _ctx: &AudioContext) {
+        unsafe { audio_source_resume(self.0) }
+    }
+
     pub fn set_volume(&self, _ctx: &AudioContext, volume: f32) {
         unsafe { audio_source_set_volume(self.0, volume) }
     }
 
+    /// Length of the sound, in seconds.
+    /// 0 until the sound `is_loaded`.
+    pub fn duration(&self, _ctx: &AudioContext) -> f32 {
+        unsafe { audio_source_duration(self.0) }
+    }
+
     pub fn delete(&self, _ctx: &AudioContext) {
         unsafe { audio_source_delete(self.0) }
     }
@@ -0,0 +1,118 @@
+//! Reverb zones, blending a reverb between presets as the listener moves.
+
+use crate::{
+    effects::ReverbSettings,
+    spatial::{length, sub},
+    Listener,
+};
+
+/// Volume of space covered by a `ReverbZone`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ZoneShape {
+    /// Axis-aligned box between two opposite corners.
+    Box {
+        min: [f32; 3],
+        max: [f32; 3],
+    },
+    Sphere {
+        center: [f32; 3],
+        radius: f32,
+    },
+}
+
+impl ZoneShape {
+    /// Distance from `position` to the shape, 0.0 inside.
+    fn distance(&self, position: [f32; 3]) -> f32 {
+        match *self {
+            ZoneShape::Box { min, max } => {
+                let outside = [0, 1, 2].map(|axis| {
+                    (min[axis] - position[axis])
+                        .max(position[axis] - max[axis])
+                        .max(0.0)
+                });
+
+                length(outside)
+            }
+            ZoneShape::Sphere { center, radius } => {
+                (length(sub(position, center)) - radius).max(0.0)
+            }
+        }
+    }
+}
+
+/// An area with its own reverb, set with `Effect::set_reverb_zones`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ReverbZone {
+    pub shape: ZoneShape,
+    pub settings: ReverbSettings,
+    /// Distance outside of the shape over which the zone fades out, for smooth transitions.
+    pub fade: f32,
+}
+
+impl ReverbZone {
+    pub fn new(shape: ZoneShape, settings: ReverbSettings) -> ReverbZone {
+        ReverbZone {
+            shape,
+            settings,
+            fade: 2.0,
+        }
+    }
+
+    /// How much the zone applies at `position`, 1.0 inside, fading to 0.0 outside.
+    fn weight(&self, position: [f32; 3]) -> f32 {
+        let distance = self.shape.distance(position);
+
+        if distance == 0.0 {
+            1.0
+        } else if self.fade > 0.0 {
+            (1.0 - distance / self.fade).max(0.0)
+        } else {
+            0.0
+        }
+    }
+}
+
+/// Zones driving the parameters of one `Reverb` effect.
+#[derive(Debug, Clone)]
+pub(crate) struct ZonedReverb {
+    pub effect: u32,
+    pub outside: ReverbSettings,
+    pub zones: Vec<ReverbZone>,
+    /// Settings last sent to the effect.
+    pub current: Option<ReverbSettings>,
+}
+
+impl ZonedReverb {
+    /// Reverb settings heard by the listeners, averaged over all of them.
+    ///
+    /// Every zone is crossfaded over the result of the zones before it,
+    /// starting from `outside`, so later zones take precedence where they overlap.
+    pub fn settings(&self, listeners: &[Listener]) -> ReverbSettings {
+        let mut average = self.outside;
+
+        for (i, listener) in listeners.iter().enumerate() {
+            let mut settings = self.outside;
+            for zone in &self.zones {
+                settings = lerp(&settings, &zone.settings, zone.weight(listener.position));
+            }
+
+            // running mean
+            average = lerp(&average, &settings, 1.0 / (i + 1) as f32);
+        }
+
+        average
+    }
+}
+
+fn lerp(a: &ReverbSettings, b: &ReverbSettings, t: f32) -> ReverbSettings {
+    let mix = |a: f32, b: f32| a + (b - a) * t;
+
+    ReverbSettings {
+        room_size: mix(a.room_size, b.room_size),
+        damping: mix(a.damping, b.damping),
+        pre_delay: mix(a.pre_delay, b.pre_delay),
+        width: mix(a.width, b.width),
+        wet: mix(a.wet, b.wet),
+        dry: mix(a.dry, b.dry),
+    }
+}